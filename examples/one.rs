@@ -4,48 +4,59 @@ extern crate coinbase_pro_one_rs;
 extern crate log;
 
 use async_std::{task};
-use std::fs::File;
-use std::io::BufReader;
+use std::path::Path;
 
 use coinbase_pro_one_rs::*;
 use coinbase_pro_one_rs::book::MsgHarvester;
+use coinbase_pro_one_rs::credentials::{CredentialProvider, FileCredentials};
 
 fn main() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let file = File::open("./credentials.json")?;
-    let reader = BufReader::new(file);
-    let credentials: Option<structs::Credentials> =
-        serde_json::from_reader(reader)?;
-
-    let (url, ws_url) = if credentials.is_some() {
-        (MAIN_URL, WS_URL)
-    } else {
-        (SANDBOX_URL, WS_SANDBOX_URL)
-    };
     // Sync
     env_logger::init();
     debug!("One: starting");
 
+    // Without a credentials file, use the sandbox's public endpoints.  A file which is
+    // there but unusable is worth hearing about.
+    let path = Path::new("./credentials.json");
+    let credentials = if path.exists() {
+        match FileCredentials::new(path).credentials() {
+            Ok(credentials) => Some(credentials),
+            Err(e) => {
+                warn!("One: ignoring {:?}: {:?}", path, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let config = if credentials.is_some() {
+        config::ConduitConfig::production()
+    } else {
+        config::ConduitConfig::sandbox()
+    };
+
     // Async so that conduit methods aren't wait-y.
     task::block_on(async {
-        let (mut conduit, mailbox) = conduit::Conduit::new(
-            url, ws_url, credentials).await;
+        let (conduit, mailbox) = conduit::Conduit::new(
+            config, credentials).await;
 
-        let mut btc_order_book: Box<dyn MsgHarvester> = Box::new(book::l2::OrderBook::new("BTC-USD".to_string()));
+        let product_ids = vec!("BTC-USD".to_string(), "ETH-USD".to_string());
+        let mut order_books: Box<dyn MsgHarvester> = Box::new(book::l2::OrderBooks::new(&product_ids));
         let mut ticker: Box<dyn MsgHarvester> = Box::new(book::ticker::Ticker::new("BTC-USD".to_string()));
-        let product_ids = vec!("BTC-USD".to_string());
 
-        conduit.interval(2500);                 // NONE
-        conduit.level(structs::Level::Level2).await;  // WS
-        conduit.ticker(product_ids).await;            // WS
-        conduit.time().await;                         // HTTP
-        conduit.heartbeat().await;                    // WS
+        conduit.interval(2500);                                           // NONE
+        conduit.level(structs::Level::Level2, product_ids.clone()).await; // WS
+        conduit.ticker(vec!("BTC-USD".to_string())).await;                // WS
+        conduit.time().await;                                             // HTTP
+        conduit.heartbeat(product_ids).await;                             // WS
         while let msg = mailbox.recv().await {
             match msg {
                 Err(e) => debug!("Match _msg err: {:?}", e),
                 Ok(m) => {
                     // Thread the `m` through the MsgHarvesters.
                     let msg = book::harvest(m, vec!(
-                        &mut btc_order_book,
+                        &mut order_books,
                         &mut ticker,
                     ));
                     match msg {
@@ -1,6 +1,8 @@
 use ordered_float::OrderedFloat;
 use std::{ collections::BTreeMap };
 
+use super::MsgHarvester;
+
 use crate::structs;
 
 
@@ -46,6 +48,12 @@ impl OrderBook {
         self.product_id == *pid
     }
 
+    /// Forgets every level (e.g. when the feed drops) until the next snapshot.
+    pub fn clear(&mut self) {
+        self.bid_book.0.clear();
+        self.ask_book.0.clear();
+    }
+
 
     fn ingest_snapshot(&mut self,
                        product_id: String,
@@ -54,6 +62,8 @@ impl OrderBook {
         if !self.match_product_id(&product_id) {
             Some(structs::Message::WSSnapshot {product_id, bids, asks})
         } else {
+            // A snapshot (e.g. after a reconnect) replaces the book.
+            self.clear();
             let _ = bids.iter().map(|item| {
                 self.bid_book.ingest(item.price, item.size);
             }).collect::<Vec<_>>();
@@ -88,7 +98,7 @@ impl OrderBook {
     }
 }
 
-impl super::MsgHarvester for OrderBook {
+impl MsgHarvester for OrderBook {
     fn harvest(&mut self, msg: structs::Message) -> Option<structs::Message> {
         match msg {
             structs::Message::WSSnapshot{product_id, bids, asks} => {
@@ -97,9 +107,79 @@ impl super::MsgHarvester for OrderBook {
             structs::Message::WSL2update{product_id, time, changes} => {
                 self.ingest_updates(product_id, time, changes)
             },
+            // Pass it on: other harvesters need to know too.
+            msg @ structs::Message::WSDisconnected{..} => {
+                self.clear();
+                Some(msg)
+            },
             msg => {
                 Some(msg)
             }
         }
     }
 }
+
+/// One `OrderBook` per product, fed by a single harvester.
+#[derive(Debug)]
+pub struct OrderBooks {
+    books: BTreeMap<String, OrderBook>,
+}
+
+impl OrderBooks {
+    pub fn new(product_ids: &[String]) -> Self {
+        Self {
+            books: product_ids.iter()
+                .map(|product_id| (product_id.clone(), OrderBook::new(product_id.clone())))
+                .collect()
+        }
+    }
+
+    pub fn get(&self, product_id: &str) -> Option<&OrderBook> {
+        self.books.get(product_id)
+    }
+
+    pub fn product_ids(&self) -> Vec<String> {
+        self.books.keys().cloned().collect()
+    }
+}
+
+impl MsgHarvester for OrderBooks {
+    fn harvest(&mut self, msg: structs::Message) -> Option<structs::Message> {
+        let book = match &msg {
+            structs::Message::WSSnapshot{product_id, ..} |
+            structs::Message::WSL2update{product_id, ..} => self.books.get_mut(product_id),
+            structs::Message::WSDisconnected{..} => {
+                self.books.values_mut().for_each(OrderBook::clear);
+                return Some(msg);
+            },
+            _ => None
+        };
+        match book {
+            Some(book) => book.harvest(msg),
+            None => Some(msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_books() {
+        let mut books = OrderBooks::new(&["BTC-USD".to_string(), "ETH-USD".to_string()]);
+        let snapshot = |product_id: &str| structs::Message::WSSnapshot {
+            product_id: product_id.to_string(),
+            bids: vec!(structs::Level2SnapshotRecord { price: 100.0, size: 1.0 }),
+            asks: vec!(structs::Level2SnapshotRecord { price: 101.0, size: 2.0 }),
+        };
+        assert!(books.harvest(snapshot("ETH-USD")).is_none());
+        assert!(books.harvest(snapshot("LTC-USD")).is_some());
+        assert!(books.get("BTC-USD").unwrap().bid_book.0.is_empty());
+        assert_eq!(books.get("ETH-USD").unwrap().ask_book.0.get(&OrderedFloat(101.0)), Some(&2.0));
+
+        let disconnected = structs::Message::WSDisconnected { reason: "test".to_string() };
+        assert!(books.harvest(disconnected).is_some());
+        assert!(books.get("ETH-USD").unwrap().ask_book.0.is_empty());
+    }
+}
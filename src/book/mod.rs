@@ -15,6 +15,24 @@ pub struct Book<T> {
     pub asks: Vec<T>,
 }
 
+/// A REST book snapshot at any level (see `Conduit::order_book`).
+#[derive(Debug, PartialEq)]
+pub enum BookSnapshot {
+    Level1(Book<BookRecordL1>),
+    Level2(Book<BookRecordL2>),
+    Level3(Book<BookRecordL3>),
+}
+
+impl BookSnapshot {
+    pub fn sequence(&self) -> usize {
+        match self {
+            BookSnapshot::Level1(book) => book.sequence,
+            BookSnapshot::Level2(book) => book.sequence,
+            BookSnapshot::Level3(book) => book.sequence,
+        }
+    }
+}
+
 pub trait BookLevel {
     fn level() -> u8;
 }
@@ -4,222 +4,953 @@ use async_std::sync::{Arc, Mutex};
 use async_std::{ task, stream::interval, sync::{ channel, Receiver, Sender }};
 use async_tungstenite::{ async_std::{ connect_async },
                          tungstenite::{protocol::Message as TMessage }};
-use crypto::{hmac::Hmac, mac::Mac};
+use chrono::SecondsFormat;
 use futures::{SinkExt, StreamExt};
 use futures_util::{FutureExt};
 use serde_json;
-use std::{time::{ Duration, SystemTime, UNIX_EPOCH }};
+use std::{sync::{ Weak, atomic::{ AtomicI64, Ordering }},
+          time::{ Duration, SystemTime, UNIX_EPOCH }};
 use surf;
+use uuid::Uuid;
 
 // LOCAL IMPORTS
+use crate::book;
+use crate::config::ConduitConfig;
+use crate::credentials::Secret;
 use crate::structs::*;
-use crate::errors::{CBProError};
+use crate::errors::{CBError, CBProError};
+use crate::limiter::{Limiter, QueueDepth, RateLimit};
+use crate::retry::RetryPolicy;
+use crate::signer::{HmacSigner, Signer};
+use crate::subscriptions::Subscriptions;
 
-const CHANNEL_SIZE: usize = 128;
+const MAX_CANDLES: i64 = 300;
+// How long a WebSocket connection must stay up before its drop no longer counts as a failed
+// attempt (i.e. before the reconnect backoff starts over).
+const HEALTHY_UPTIME: Duration = Duration::from_secs(30);
+// The endpoints which must be signed (and which count against the private rate limit).
+const PRIVATE_PATHS: &[&str] = &[
+    "/accounts",
+    "/coinbase-accounts",
+    "/conversions",
+    "/deposits",
+    "/fees",
+    "/fills",
+    "/orders",
+    "/payment-methods",
+    "/profiles",
+    "/reports",
+    "/transfers",
+    "/users",
+    "/withdrawals",
+];
 const USER_AGENT: &str = concat!("coinbase-pro-one-rs/", env!("CARGO_PKG_VERSION"));
 
-pub struct Conduit<'a> {
+pub struct Conduit {
     last_time: u64,
     // The Coinbase REST endpoint.  Needed for all RESTy methods.
-    base_http_uri: &'a str,
-    // Coinbase credentials.
-    credentials: Option<Credentials>,
+    base_http_uri: String,
+    user_agent: String,
+    request_timeout: Duration,
+    // Signs private requests and WS subscriptions.
+    authenticator: Arc<Authenticator>,
     // To communicate with the 'user' of the library
     to_mailbox:   Arc<Mutex<Sender<Message>>>,
 
     to_websocket: Arc<Mutex<Sender<Message>>>,
+    // What's subscribed: requested here, confirmed by the WebSocket task.
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    // Client-side rate limiting for REST requests.
+    public_limiter: Arc<Limiter>,
+    private_limiter: Arc<Limiter>,
+    retry_policy: RetryPolicy,
 }
 
-impl Conduit<'static> {
+impl Conduit {
     /// Creates a new Conduit
-    pub async fn new(http_uri: &'static str, ws_uri: &'static str, _creds: Option<Credentials>)
-                     -> (Conduit<'static>, Receiver<Message>) {
-        debug!("Conduit.new: {:?} {:?} {:?}", http_uri, ws_uri, _creds);
-        // Creates a new Conduit
-        let credentials = if _creds.is_some() {
-            let creds = _creds.unwrap();
-            Some(Credentials {
-                key: creds.key,
-                secret: creds.secret,
-                passphrase: creds.passphrase
-            })
-        } else {
-            Option::None
+    pub async fn new(config: ConduitConfig, _creds: Option<Credentials>)
+                     -> (Conduit, Receiver<Message>) {
+        // Signs with the secret in-process.
+        let identity = _creds.map(|creds| Identity {
+            key: creds.key,
+            passphrase: creds.passphrase,
+            signer: Arc::new(HmacSigner::new(creds.secret))
+        });
+        Self::_new(config, identity).await
+    }
+
+    /// Creates a new Conduit which signs with `signer` (e.g. a signing service which holds
+    /// the API secret) rather than with the secret in-process.
+    pub async fn with_signer(config: ConduitConfig, key: String, passphrase: Secret,
+                             signer: Arc<dyn Signer>) -> (Conduit, Receiver<Message>) {
+        Self::_new(config, Some(Identity { key, passphrase, signer })).await
+    }
+
+    async fn _new(config: ConduitConfig, identity: Option<Identity>)
+                  -> (Conduit, Receiver<Message>) {
+        debug!("Conduit.new: {:?} (credentials: {})", config, identity.is_some());
+        let user_agent = match &config.user_agent_suffix {
+            Some(suffix) => format!("{} {}", USER_AGENT, suffix),
+            None => USER_AGENT.to_string()
         };
 
-        let (_to_mailbox, mailbox) = channel::<Message>(CHANNEL_SIZE);
+        // `channel` panics on a capacity of 0, which the (public) field doesn't rule out.
+        let capacity = config.mailbox_capacity.max(1);
+        let (_to_mailbox, mailbox) = channel::<Message>(capacity);
         let to_mailbox = Arc::new(Mutex::new(_to_mailbox));
-        let (to_websocket, __to_websocket) = channel::<Message>(CHANNEL_SIZE);
-        let _to_websocket = Arc::new(Mutex::new(__to_websocket));
+        let (to_websocket, from_conduit) = channel::<Message>(capacity);
+        // Signatures are only valid within 30s of Coinbase's clock...
+        let skew = Arc::new(AtomicI64::new(0));
+        match _measure_skew(&config.http_url, &user_agent, config.request_timeout).await {
+            Ok(ms) => skew.store(ms, Ordering::SeqCst),
+            Err(e) => warn!("Conduit: can't measure clock skew: {:?}", e)
+        }
+        handle_clock(config.http_url.clone(), user_agent.clone(), config.clock_sync_interval, config.request_timeout,
+                     Arc::downgrade(&skew));
+        let authenticator = Arc::new(Authenticator { identity, skew });
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::new()));
         debug!("ConduitWebsocket: starting...");
         handle_websocket(
-            ws_uri,
-           to_mailbox.clone(),
-           Arc::clone(&_to_websocket));
+            config.ws_url.clone(),
+            Arc::clone(&authenticator),
+            Arc::clone(&subscriptions),
+            config.reconnect_policy,
+            config.request_timeout,
+            to_mailbox.clone(),
+            from_conduit);
 
         (Self {
             last_time: _timestamp(),
-            base_http_uri: http_uri,
-            credentials: credentials.clone(),
+            base_http_uri: config.http_url,
+            user_agent,
+            request_timeout: config.request_timeout,
+            authenticator,
             to_mailbox,
             to_websocket: Arc::new(Mutex::new(to_websocket)),
+            subscriptions,
+            public_limiter: Arc::new(Limiter::new(config.public_rate_limit)),
+            private_limiter: Arc::new(Limiter::new(config.private_rate_limit)),
+            retry_policy: config.retry_policy,
         }, mailbox)
     }
 
-    pub fn sign(&self, method: surf::http_types::Method, uri: &str, body: Option<serde_json::Value>) -> (String, u64) {
-        let ts = _timestamp();
-        let secret = base64::decode(&self.credentials.clone().unwrap().secret).expect("base64::decode secret");
-        let mut mac = Hmac::new(crypto::sha2::Sha256::new(), &secret);
-        // Make the borrow_checker happy...
-        let mut unwrapped_body: String = match body {
-            None => String::from(""),
-            Some(v) => v.to_string()
-        };
-        mac.input((ts.to_string() + method.as_ref() + uri + unwrapped_body.as_str()).as_bytes());
-        (base64::encode(&mac.result().code()), ts)
-    }
-
-    fn auth(&self, method: surf::http_types::Method, path: &str, body: Option<serde_json::Value>) -> Option<Auth> {
-        match &self.credentials {
-            Some(c) => {
-                debug!("Conduit: calculating auth...");
-                let (signature, ts) = self.sign(method, path, body);
-                Some(
-                    Auth {
-                        signature: signature,
-                        key: c.key.to_string(),
-                        passphrase: c.passphrase.to_string(),
-                        timestamp: ts.to_string()
-                    }
-                )
-            },
-            None => {
-                debug!("Conduit: **not** calculating auth... ");
-                None
-            }
+    /// The last measured offset of Coinbase's clock from ours (positive when ours is behind).
+    /// Re-measured every `ConduitConfig::clock_sync_interval`.
+    pub fn clock_skew(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(self.authenticator.skew.load(Ordering::SeqCst))
+    }
+
+    /// The number of REST requests currently waiting on the rate limiters.
+    pub fn queue_depth(&self) -> QueueDepth {
+        QueueDepth {
+            public: self.public_limiter.queued(),
+            private: self.private_limiter.queued(),
         }
     }
 
+    pub async fn sign(&self, method: surf::http_types::Method, uri: &str, body: Option<serde_json::Value>)
+                      -> Result<(String, u64), CBProError> {
+        self.authenticator.sign(method, uri, body).await
+    }
+
     //////////////////////////////////////////////////////////////
     /// Subscribe a Conduit to the Coinbase WS endpoint.
-    pub async fn subscribe(&mut self, channels: &[WSChannel]) {
+    ///
+    /// Subscriptions survive reconnects: the WebSocket replays them (re-signed) after
+    /// reconnecting and then sends a `Message::WSConnected`.
+    pub async fn subscribe(&self, channels: &[WSChannel]) {
+        // Without credentials, only the public channels are available.
+        let auth = match self.authenticator.ws_auth().await {
+            Ok(auth) => auth,
+            Err(e) => {
+                self._send(Err(e)).await;
+                return;
+            }
+        };
         let subscribe = WSSubscribe {
             channels: channels.to_vec(),
-            auth: self.auth(surf::http_types::Method::Get, "/users/self/verify", None)
+            auth
         };
 
+        // Recorded now, so that `subscriptions` reflects it at once (and a reconnect replays it).
+        self.subscriptions.lock().await.subscribe(channels);
         let msg = Message::WSSubscribe(subscribe);
-        debug!("Conduit: subscription: sending {:?}", serde_json::to_string(&msg).unwrap());
+        debug!("Conduit: subscription: sending {:?}", &msg);
         self.to_websocket.lock().await.send(msg).await;
     }
 
+    /// Unsubscribe from channels.  A bare `WSChannel::Name` drops the whole channel; a
+    /// `WSChannel::WithProduct` drops just its products.
+    pub async fn unsubscribe(&self, channels: &[WSChannel]) {
+        self.subscriptions.lock().await.unsubscribe(channels);
+        let msg = Message::WSUnsubscribe(WSUnsubscribe { channels: channels.to_vec() });
+        debug!("Conduit: unsubscription: sending {:?}", &msg);
+        self.to_websocket.lock().await.send(msg).await;
+    }
+
+    /// What has been requested versus what Coinbase last confirmed (see
+    /// `Subscriptions::pending` and `Subscriptions::unexpected`).
+    pub async fn subscriptions(&self) -> Subscriptions {
+        self.subscriptions.lock().await.clone()
+    }
+
     /// **Core Requests**
     ///
     ///
     ///
-    async fn _request(&mut self,
+    async fn _call(&self,
+                   method: surf::http_types::Method,
+                   path: &str,
+                   body: Option<serde_json::Value>) -> Result<Response, CBProError> {
+        let idempotent = method != surf::http_types::Method::Post
+            || body.as_ref().and_then(|b| b.get("client_oid")).is_some();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self._attempt(method, path, body.clone()).await {
+                Err(f) if idempotent && f.error.is_retryable() && attempt < self.retry_policy.max_attempts => {
+                    // Coinbase's `Retry-After` is honoured, but no further than the policy allows.
+                    let delay = f.retry_after
+                        .map(|d| d.min(self.retry_policy.max_delay))
+                        .unwrap_or_else(|| self.retry_policy.delay(attempt));
+                    warn!("Conduit: {:?} {:?} failed (attempt {}), retrying in {:?}: {:?}",
+                          &method, path, attempt, delay, f.error);
+                    task::sleep(delay).await;
+                },
+                result => return result.map_err(|f| f.error)
+            }
+        }
+    }
+
+    async fn _attempt(&self,
                       method: surf::http_types::Method,
-                      serde_type: &str,
                       path: &str,
-                      body: Option<serde_json::Value>) {
-        debug!("Conduit: _request: {:?} {:?} {:?} {:?}", &method, path, &body, serde_type);
-        let mut req =
-            surf::Request::new(
-                method,
-                url::Url::parse(&*format!("{}{}", self.base_http_uri, path)).unwrap()
-            ).set_header("User-Agent", USER_AGENT);
-        if body.is_some() {
-            let auth = self.auth(method, path, body.clone()).unwrap();
+                      body: Option<serde_json::Value>) -> Result<Response, Failure> {
+        debug!("Conduit: _attempt: {:?} {:?} {:?}", &method, path, &body);
+        // Wait for the limiter before signing so that the signature is fresh.
+        // ...but fail fast, without taking a token, if a private request can't be signed.
+        let private = _is_private(path);
+        if private {
+            self.authenticator.identity(method, path)?;
+            self.private_limiter.acquire().await;
+        } else {
+            self.public_limiter.acquire().await;
+        }
+        let url = url::Url::parse(&*format!("{}{}", self.base_http_uri, path))
+            .map_err(|e| CBProError::Message(e.to_string()))?;
+        let mut req = surf::Request::new(method, url).set_header("User-Agent", self.user_agent.as_str());
+        if private {
+            let auth = self.authenticator.auth(method, path, body.clone()).await?;
             req = req.set_header("CB-ACCESS-KEY", auth.key)
                .set_header("CB-ACCESS-SIGN", auth.signature)
-               .set_header("CB-ACCESS-PASSPHRASE", auth.passphrase)
-               .set_header("CB-ACCESS-TIMESTAMP", auth.timestamp.to_string())
-               .body_json(&body).unwrap();
+               .set_header("CB-ACCESS-PASSPHRASE", auth.passphrase.expose())
+               .set_header("CB-ACCESS-TIMESTAMP", auth.timestamp.to_string());
+        }
+        if body.is_some() {
+            req = req.body_json(&body).map_err(|e| CBProError::Serde(e.to_string()))?;
+        }
+        let mut resp = async_std::future::timeout(self.request_timeout, req).await
+            .map_err(|_| CBProError::Http(format!("timed out after {:?}", self.request_timeout)))?
+            .map_err(|e| CBProError::Http(e.to_string()))?;
+        let body = resp.body_string().await.map_err(|e| CBProError::Read(e.to_string()))?;
+        let status = resp.status();
+        if !status.is_success() {
+            let retry_after = resp.header("retry-after")
+                .and_then(|v| v.as_str().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err(Failure {
+                error: CBProError::Coinbase(CBError::new(status.into(), path, body.as_str())),
+                retry_after
+            });
         }
-        // Can't yet convert to a `Message` because we haven't applied the serde_type yet...
-        let resp = req.recv_string().await;
-
-        let msg = if !(resp.is_err()) {
-            let mut _resp = serde_json::from_str::<serde_json::Value>(resp.unwrap().as_str()).unwrap();
-            _resp["type"] = serde_json::Value::String(serde_type.to_string());
-            serde_json::from_value(_resp).unwrap_or_else(|e| {
-                Message::InternalError(CBProError::Serde(e.to_string()))
+        // Paginated endpoints return their cursors in headers.
+        let cursor = |name: &str| resp.header(name).map(|v| v.as_str().to_string());
+        Ok(Response { before: cursor("cb-before"), after: cursor("cb-after"), body })
+    }
+
+    async fn _request<T>(&self,
+                         method: surf::http_types::Method,
+                         path: &str,
+                         body: Option<serde_json::Value>) -> Result<T, CBProError>
+        where for<'de> T: serde::Deserialize<'de> {
+        let resp = self._call(method, path, body).await?;
+        // Some endpoints answer with an empty body.
+        let body = if resp.body.trim().is_empty() { "null" } else { resp.body.as_str() };
+        serde_json::from_str(body).map_err(|e| CBProError::Serde(e.to_string()))
+    }
+
+    async fn _request_page<T>(&self, path: &str, params: &[(&str, Option<String>)], after: Option<String>)
+        -> Result<Page<T>, CBProError>
+        where for<'de> T: serde::Deserialize<'de> {
+        let mut query = params.to_vec();
+        query.push(("after", after));
+        let path = format!("{}{}", path, _query(&query));
+        let resp = self._call(surf::http_types::Method::Get, &path, Option::None).await?;
+        let items = serde_json::from_str(resp.body.as_str()).map_err(|e| CBProError::Serde(e.to_string()))?;
+        Ok(Page { items, before: resp.before, after: resp.after })
+    }
+
+    /// Walks every page of `path` (oldest pages last), delivering each page to the mailbox.
+    async fn _paginate<T, F>(&self, path: &str, params: Vec<(&str, Option<String>)>, wrap: F)
+        where for<'de> T: serde::Deserialize<'de>,
+              F: Fn(Page<T>) -> Message {
+        let mut after: Option<String> = None;
+        loop {
+            match self._request_page(path, &params, after.take()).await {
+                Ok(page) => {
+                    let done = page.items.is_empty() || page.after.is_none();
+                    after = page.after.clone();
+                    self._send(Ok(wrap(page))).await;
+                    if done {
+                        break;
+                    }
+                },
+                Err(e) => {
+                    self._send(Err(e)).await;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Walks every page of `path` (oldest pages last), collecting the items.
+    async fn _fetch_all<T>(&self, path: &str, params: Vec<(&str, Option<String>)>) -> Result<Vec<T>, CBProError>
+        where for<'de> T: serde::Deserialize<'de> {
+        let mut items: Vec<T> = vec!();
+        let mut after: Option<String> = None;
+        loop {
+            let page = self._request_page(path, &params, after.take()).await?;
+            let done = page.items.is_empty() || page.after.is_none();
+            items.extend(page.items);
+            after = page.after;
+            if done {
+                return Ok(items);
+            }
+        }
+    }
+
+    async fn _get<T>(&self, uri: &str) -> Result<T, CBProError>
+        where for<'de> T: serde::Deserialize<'de> {
+        self._request(surf::http_types::Method::Get, uri, Option::None).await
+    }
+
+    async fn _delete<T>(&self, uri: &str) -> Result<T, CBProError>
+        where for<'de> T: serde::Deserialize<'de> {
+        self._request(surf::http_types::Method::Delete, uri, Option::None).await
+    }
+
+    async fn _post<T>(&self, uri: &str, body: serde_json::Value) -> Result<T, CBProError>
+        where for<'de> T: serde::Deserialize<'de> {
+        self._request(surf::http_types::Method::Post, uri, Some(body)).await
+    }
+
+    /// Delivers the result of a REST call to the mailbox.
+    ///
+    /// Every mailbox method (e.g. `accounts`) has an awaitable twin (e.g. `fetch_accounts`)
+    /// which returns the typed result to the caller instead.
+    async fn _send(&self, msg: Result<Message, CBProError>) {
+        let msg = msg.unwrap_or_else(Message::InternalError);
+        self.to_mailbox.lock().await.send(msg).await;
+    }
+
+
+    /// **Accounts**
+    ///
+    /// Requires `Credentials` with the "view" or "trade" permission.
+    pub async fn accounts(&self) {
+        debug!("Conduit: accounts sent...");
+        let msg = self.fetch_accounts().await.map(Message::Accounts);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_accounts(&self) -> Result<Vec<Account>, CBProError> {
+        self._get("/accounts").await
+    }
+
+    pub async fn account(&self, account_id: Uuid) {
+        debug!("Conduit: account sent...");
+        let msg = self.fetch_account(account_id).await.map(Message::Account);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_account(&self, account_id: Uuid) -> Result<Account, CBProError> {
+        self._get(&format!("/accounts/{}", account_id)).await
+    }
+
+    /// Ledger entries are paginated and sorted latest first; this is the first page.
+    pub async fn account_history(&self, account_id: Uuid) {
+        debug!("Conduit: account_history sent...");
+        let msg = self.fetch_account_history(account_id).await.map(Message::AccountHistory);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_account_history(&self, account_id: Uuid) -> Result<Vec<AccountHistory>, CBProError> {
+        self._get(&format!("/accounts/{}/ledger", account_id)).await
+            .map(|xs: Vec<AccountHistory>| {
+                xs.into_iter()
+                    .map(|x| AccountHistory { _type: (&x.details).into(), ..x })
+                    .collect()
             })
-        } else {
-            Message::InternalError(CBProError::Http(resp.err().unwrap().to_string()))
+    }
+
+    pub async fn account_holds(&self, account_id: Uuid) {
+        debug!("Conduit: account_holds sent...");
+        let msg = self.fetch_account_holds(account_id).await.map(Message::AccountHolds);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_account_holds(&self, account_id: Uuid) -> Result<Vec<AccountHolds>, CBProError> {
+        self._get(&format!("/accounts/{}/holds", account_id)).await
+    }
+
+    /// **Orders**
+    ///
+    /// Places an order built with `Order::buy_limit`, `Order::sell_market`, etc.  The
+    /// acknowledgement arrives as a `Message::Order`; a rejection arrives as a
+    /// `Message::InternalError(CBProError::Coinbase(..))` carrying the exchange's `message`.
+    ///
+    /// Requires `Credentials` with the "trade" permission.
+    pub async fn place_order(&self, order: Order<'_>) {
+        debug!("Conduit: place_order sent...");
+        let msg = self.fetch_place_order(order).await.map(Message::Order);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_place_order(&self, order: Order<'_>) -> Result<Order<'static>, CBProError> {
+        let body = serde_json::to_value(&order).map_err(|e| CBProError::Serde(e.to_string()))?;
+        self._post("/orders", body).await
+    }
+
+    /// Cancels a previously placed order.  The ids of cancelled orders arrive as a
+    /// `Message::CancelledOrders`.
+    ///
+    /// Requires `Credentials` with the "trade" permission.
+    pub async fn cancel_order(&self, order_id: Uuid) {
+        debug!("Conduit: cancel_order sent...");
+        let msg = self.fetch_cancel_order(order_id).await.map(|id| Message::CancelledOrders(vec!(id)));
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_cancel_order(&self, order_id: Uuid) -> Result<Uuid, CBProError> {
+        self._delete(&format!("/orders/{}", order_id)).await
+    }
+
+    pub async fn cancel_order_by_client_oid(&self, client_oid: Uuid) {
+        debug!("Conduit: cancel_order_by_client_oid sent...");
+        let msg = self.fetch_cancel_order_by_client_oid(client_oid).await
+            .map(|id| Message::CancelledOrders(vec!(id)));
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_cancel_order_by_client_oid(&self, client_oid: Uuid) -> Result<Uuid, CBProError> {
+        self._delete(&format!("/orders/client:{}", client_oid)).await
+    }
+
+    /// With best effort, cancels all open orders (optionally only those for `product_id`).
+    ///
+    /// Orders are scoped to the API key's default profile unless `profile_id` is given.
+    pub async fn cancel_all(&self, product_id: Option<&str>, profile_id: Option<Uuid>) {
+        debug!("Conduit: cancel_all sent...");
+        let msg = self.fetch_cancel_all(product_id, profile_id).await.map(Message::CancelledOrders);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_cancel_all(&self, product_id: Option<&str>, profile_id: Option<Uuid>)
+        -> Result<Vec<Uuid>, CBProError> {
+        let params = [("product_id", product_id.map(String::from)),
+                      ("profile_id", profile_id.map(|x| x.to_string()))];
+        self._delete(&format!("/orders{}", _query(&params))).await
+    }
+
+    /// Lists orders, walking every page.  Each page arrives as a `Message::Orders`.
+    ///
+    /// `status` defaults (at Coinbase) to open, pending and active orders.
+    pub async fn orders(&self, status: Option<OrderStatus>, product_id: Option<&str>, profile_id: Option<Uuid>) {
+        debug!("Conduit: orders sent...");
+        let params = vec!(("status", status.map(|x| x.to_string())),
+                          ("product_id", product_id.map(String::from)),
+                          ("profile_id", profile_id.map(|x| x.to_string())));
+        self._paginate("/orders", params, Message::Orders).await;
+    }
+
+    /// As `orders` but returns every page's orders together.
+    pub async fn fetch_orders(&self, status: Option<OrderStatus>, product_id: Option<&str>, profile_id: Option<Uuid>)
+        -> Result<Vec<Order<'static>>, CBProError> {
+        let params = vec!(("status", status.map(|x| x.to_string())),
+                          ("product_id", product_id.map(String::from)),
+                          ("profile_id", profile_id.map(|x| x.to_string())));
+        self._fetch_all("/orders", params).await
+    }
+
+    /// Lists fills, walking every page.  Each page arrives as a `Message::Fills`.
+    ///
+    /// Coinbase requires at least one of `order_id` or `product_id`.
+    pub async fn fills(&self, order_id: Option<Uuid>, product_id: Option<&str>, profile_id: Option<Uuid>) {
+        debug!("Conduit: fills sent...");
+        let params = vec!(("order_id", order_id.map(|x| x.to_string())),
+                          ("product_id", product_id.map(String::from)),
+                          ("profile_id", profile_id.map(|x| x.to_string())));
+        self._paginate("/fills", params, Message::Fills).await;
+    }
+
+    /// As `fills` but returns every page's fills together.
+    pub async fn fetch_fills(&self, order_id: Option<Uuid>, product_id: Option<&str>, profile_id: Option<Uuid>)
+        -> Result<Vec<Fill>, CBProError> {
+        let params = vec!(("order_id", order_id.map(|x| x.to_string())),
+                          ("product_id", product_id.map(String::from)),
+                          ("profile_id", profile_id.map(|x| x.to_string())));
+        self._fetch_all("/fills", params).await
+    }
+
+    /// **Profiles**
+    ///
+    /// Lists the user's profiles (only the active ones when `active` is `Some(true)`).
+    /// Orders select a profile with `Order::profile_id`; listings take a `profile_id`.
+    pub async fn profiles(&self, active: Option<bool>) {
+        debug!("Conduit: profiles sent...");
+        let msg = self.fetch_profiles(active).await.map(Message::Profiles);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_profiles(&self, active: Option<bool>) -> Result<Vec<Profile>, CBProError> {
+        let params = [("active", active.map(|x| x.to_string()))];
+        self._get(&format!("/profiles{}", _query(&params))).await
+    }
+
+    pub async fn profile(&self, profile_id: Uuid) {
+        debug!("Conduit: profile sent...");
+        let msg = self.fetch_profile(profile_id).await.map(Message::Profile);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_profile(&self, profile_id: Uuid) -> Result<Profile, CBProError> {
+        self._get(&format!("/profiles/{}", profile_id)).await
+    }
+
+    /// Moves funds between two profiles.  On success the transfer is echoed back as a
+    /// `Message::ProfileTransfer`.
+    ///
+    /// Requires `Credentials` with the "transfer" permission.
+    pub async fn profile_transfer(&self, transfer: ProfileTransfer) {
+        debug!("Conduit: profile_transfer sent...");
+        let msg = self.fetch_profile_transfer(&transfer).await.map(|()| Message::ProfileTransfer(transfer));
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_profile_transfer(&self, transfer: &ProfileTransfer) -> Result<(), CBProError> {
+        let body = serde_json::to_value(transfer).map_err(|e| CBProError::Serde(e.to_string()))?;
+        // Any 2xx means the funds moved, whatever the body says; it isn't parsed so that an
+        // unexpected one can't make a completed transfer look failed (and get retried).
+        self._call(surf::http_types::Method::Post, "/profiles/transfer", Some(body)).await.map(|_| ())
+    }
+
+    /// **Deposits and Withdrawals**
+    ///
+    /// Lists deposits and withdrawals (optionally of one `TransferType`), walking every page.
+    /// Each page arrives as a `Message::Transfers`.
+    pub async fn transfers(&self, _type: Option<TransferType>, profile_id: Option<Uuid>) {
+        debug!("Conduit: transfers sent...");
+        let params = vec!(("type", _type.map(|x| x.to_string())),
+                          ("profile_id", profile_id.map(|x| x.to_string())));
+        self._paginate("/transfers", params, Message::Transfers).await;
+    }
+
+    /// As `transfers` but returns every page's transfers together.
+    pub async fn fetch_transfers(&self, _type: Option<TransferType>, profile_id: Option<Uuid>)
+        -> Result<Vec<Transfer>, CBProError> {
+        let params = vec!(("type", _type.map(|x| x.to_string())),
+                          ("profile_id", profile_id.map(|x| x.to_string())));
+        self._fetch_all("/transfers", params).await
+    }
+
+    /// Withdraws to a crypto address.  The receipt arrives as a `Message::TransferReceipt`.
+    /// Withdrawals are never retried.
+    ///
+    /// Requires `Credentials` with the "transfer" permission.
+    pub async fn withdraw_crypto(&self, withdrawal: CryptoWithdrawal) {
+        debug!("Conduit: withdraw_crypto sent...");
+        let msg = self.fetch_withdraw_crypto(&withdrawal).await.map(Message::TransferReceipt);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_withdraw_crypto(&self, withdrawal: &CryptoWithdrawal) -> Result<TransferReceipt, CBProError> {
+        let body = serde_json::to_value(withdrawal).map_err(|e| CBProError::Serde(e.to_string()))?;
+        self._post("/withdrawals/crypto", body).await
+    }
+
+    /// The network fee Coinbase expects to charge for withdrawing `currency` to `crypto_address`.
+    pub async fn withdrawal_fee_estimate(&self, currency: &str, crypto_address: &str) {
+        debug!("Conduit: withdrawal_fee_estimate sent...");
+        let msg = self.fetch_withdrawal_fee_estimate(currency, crypto_address).await
+            .map(Message::WithdrawalFeeEstimate);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_withdrawal_fee_estimate(&self, currency: &str, crypto_address: &str)
+        -> Result<WithdrawalFeeEstimate, CBProError> {
+        let params = [("currency", Some(currency.to_string())),
+                      ("crypto_address", Some(crypto_address.to_string()))];
+        self._get(&format!("/withdrawals/fee-estimate{}", _query(&params))).await
+    }
+
+    /// Generates a new crypto deposit address for a Coinbase account.  The address arrives as
+    /// a `Message::DepositAddress`.
+    pub async fn deposit_address(&self, coinbase_account_id: Uuid) {
+        debug!("Conduit: deposit_address sent...");
+        let msg = self.fetch_deposit_address(coinbase_account_id).await.map(Message::DepositAddress);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_deposit_address(&self, coinbase_account_id: Uuid) -> Result<DepositAddress, CBProError> {
+        let body = serde_json::Value::Object(serde_json::Map::new());
+        self._post(&format!("/coinbase-accounts/{}/addresses", coinbase_account_id), body).await
+    }
+
+    /// Deposits from a Coinbase account.  The receipt arrives as a `Message::TransferReceipt`.
+    ///
+    /// Requires `Credentials` with the "transfer" permission.
+    pub async fn deposit_coinbase_account(&self, deposit: CoinbaseAccountDeposit) {
+        debug!("Conduit: deposit_coinbase_account sent...");
+        let msg = self.fetch_deposit_coinbase_account(&deposit).await.map(Message::TransferReceipt);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_deposit_coinbase_account(&self, deposit: &CoinbaseAccountDeposit)
+        -> Result<TransferReceipt, CBProError> {
+        let body = serde_json::to_value(deposit).map_err(|e| CBProError::Serde(e.to_string()))?;
+        self._post("/deposits/coinbase-account", body).await
+    }
+
+    /// Deposits from a payment method (e.g. a bank account).  The receipt arrives as a
+    /// `Message::TransferReceipt`.
+    ///
+    /// Requires `Credentials` with the "transfer" permission.
+    pub async fn deposit_payment_method(&self, deposit: PaymentMethodDeposit) {
+        debug!("Conduit: deposit_payment_method sent...");
+        let msg = self.fetch_deposit_payment_method(&deposit).await.map(Message::TransferReceipt);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_deposit_payment_method(&self, deposit: &PaymentMethodDeposit)
+        -> Result<TransferReceipt, CBProError> {
+        let body = serde_json::to_value(deposit).map_err(|e| CBProError::Serde(e.to_string()))?;
+        self._post("/deposits/payment-method", body).await
+    }
+
+    /// **Conversions and Fees**
+    ///
+    /// Converts `amount` of `from` into `to` (e.g. "USD" to "USDC") at 1:1.  The conversion
+    /// arrives as a `Message::Conversion`.
+    ///
+    /// Requires `Credentials` with the "trade" permission.
+    pub async fn convert(&self, from: &str, to: &str, amount: f64) {
+        debug!("Conduit: convert sent...");
+        let msg = self.fetch_convert(from, to, amount).await.map(Message::Conversion);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_convert(&self, from: &str, to: &str, amount: f64) -> Result<Conversion, CBProError> {
+        let body = serde_json::json!({ "from": from, "to": to, "amount": amount });
+        self._post("/conversions", body).await
+    }
+
+    /// The current maker/taker fee rates and the 30-day USD volume which sets them.
+    pub async fn fees(&self) {
+        debug!("Conduit: fees sent...");
+        let msg = self.fetch_fees().await.map(Message::Fees);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_fees(&self) -> Result<Fees, CBProError> {
+        self._get("/fees").await
+    }
+
+    /// The 30-day trailing volume, per product.
+    pub async fn trailing_volume(&self) {
+        debug!("Conduit: trailing_volume sent...");
+        let msg = self.fetch_trailing_volume().await.map(Message::TrailingVolume);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_trailing_volume(&self) -> Result<Vec<TrailingVolume>, CBProError> {
+        self._get("/users/self/trailing-volume").await
+    }
+
+    /// **Reports**
+    ///
+    /// Asks Coinbase to generate a report.  The (pending) report arrives as a `Message::Report`;
+    /// poll it with `report` until its status is `ReportStatus::Ready`.
+    pub async fn create_report(&self, request: ReportRequest) {
+        debug!("Conduit: create_report sent...");
+        let msg = self.fetch_create_report(&request).await.map(Message::Report);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_create_report(&self, request: &ReportRequest) -> Result<Report, CBProError> {
+        let body = serde_json::to_value(request).map_err(|e| CBProError::Serde(e.to_string()))?;
+        self._post("/reports", body).await
+    }
+
+    pub async fn report(&self, report_id: Uuid) {
+        debug!("Conduit: report sent...");
+        let msg = self.fetch_report(report_id).await.map(Message::Report);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_report(&self, report_id: Uuid) -> Result<Report, CBProError> {
+        self._get(&format!("/reports/{}", report_id)).await
+    }
+
+    /// Downloads a ready report's file to `writer`, returning the number of bytes written.
+    pub async fn fetch_download_report<W>(&self, report: &Report, writer: &mut W) -> Result<u64, CBProError>
+        where W: async_std::io::Write + Unpin {
+        let file_url = match (&report.status, &report.file_url) {
+            (ReportStatus::Ready, Some(file_url)) => file_url,
+            _ => return Err(CBProError::Message(format!("report {} is not ready", report.id)))
         };
-        self.to_mailbox.lock().await.send(msg).await;
+        // `file_url` is pre-signed, so the request isn't.
+        let url = url::Url::parse(file_url).map_err(|e| CBProError::Message(e.to_string()))?;
+        let req = surf::Request::new(surf::http_types::Method::Get, url)
+            .set_header("User-Agent", self.user_agent.as_str());
+        let mut resp = async_std::future::timeout(self.request_timeout, req).await
+            .map_err(|_| CBProError::Http(format!("timed out after {:?}", self.request_timeout)))?
+            .map_err(|e| CBProError::Http(e.to_string()))?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.body_string().await.map_err(|e| CBProError::Read(e.to_string()))?;
+            // Not `file_url`: its query is the signature, which shouldn't end up in logs.
+            let path = format!("/reports/{}", report.id);
+            return Err(CBProError::Coinbase(CBError::new(status.into(), &path, &body)));
+        }
+        // Reports can be large, so they're streamed rather than read into memory.
+        async_std::io::copy(&mut resp, writer).await.map_err(|e| CBProError::Read(e.to_string()))
     }
 
-    async fn _get(&mut self, serde_type: &str, uri: &str) {
-        self._request(surf::http_types::Method::Get, serde_type, uri, Option::None).await;
+    /// Creates a report, polls it every `poll_interval` until it's ready and downloads it to
+    /// `writer`.  Returns the finished report, or an error if it isn't ready within `max_wait`.
+    pub async fn fetch_generate_report<W>(&self,
+                                          request: &ReportRequest,
+                                          poll_interval: Duration,
+                                          max_wait: Duration,
+                                          writer: &mut W) -> Result<Report, CBProError>
+        where W: async_std::io::Write + Unpin {
+        let started = std::time::Instant::now();
+        let mut report = self.fetch_create_report(request).await?;
+        while report.status != ReportStatus::Ready {
+            debug!("Conduit: report {} is {:?}...", report.id, report.status);
+            if started.elapsed() + poll_interval > max_wait {
+                return Err(CBProError::Message(
+                    format!("report {} is not ready after {:?}", report.id, started.elapsed())));
+            }
+            task::sleep(poll_interval).await;
+            report = self.fetch_report(report.id).await?;
+        }
+        self.fetch_download_report(&report, writer).await?;
+        Ok(report)
     }
-    // async fn _post(&mut self, _type: &str, uri: &str, body: Option<String>) {
-    //     self._request(surf::http_types::Method::Post, uri, body, _type).await;
-    // }
 
+    /// **Candles**
+    ///
+    /// Historic rates for `product_id` between `start` and `end`.  Coinbase returns at most
+    /// 300 candles per request, so longer ranges are requested window by window and then
+    /// stitched together (oldest first, de-duplicated) into a single `Message::Candles`.
+    pub async fn candles(&self, product_id: &str, start: DateTime, end: DateTime, granularity: Granularity) {
+        debug!("Conduit: candles sent...");
+        let msg = self.fetch_candles(product_id, start, end, granularity).await.map(Message::Candles);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_candles(&self, product_id: &str, start: DateTime, end: DateTime, granularity: Granularity)
+        -> Result<Vec<Candle>, CBProError> {
+        let mut candles: Vec<Candle> = vec!();
+        for (start, end) in _candle_windows(start, end, granularity) {
+            let params = vec!(("start", Some(start.to_rfc3339_opts(SecondsFormat::Secs, true))),
+                              ("end", Some(end.to_rfc3339_opts(SecondsFormat::Secs, true))),
+                              ("granularity", Some((granularity as u64).to_string())));
+            let mut window = self._get(&format!("/products/{}/candles{}", product_id, _query(&params))).await?;
+            candles.append(&mut window);
+        }
+        candles.sort_by_key(|c| c.0);
+        candles.dedup_by_key(|c| c.0);
+        Ok(candles)
+    }
+
+    /// **Trades**
+    ///
+    /// Backfills the trades for `product_id` made after `since_trade_id` (e.g. to fill a gap
+    /// in a `matches` recording).  Pages backwards from the latest trade and delivers a single
+    /// `Message::Trades`, oldest first.
+    pub async fn trades(&self, product_id: &str, since_trade_id: usize) {
+        debug!("Conduit: trades sent...");
+        let msg = self.fetch_trades(product_id, since_trade_id).await.map(Message::Trades);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_trades(&self, product_id: &str, since_trade_id: usize) -> Result<Vec<Trade>, CBProError> {
+        self._fetch_trades(product_id, |t| t.trade_id > since_trade_id).await
+    }
+
+    /// As `trades` but backfills the trades made after `since`.
+    pub async fn trades_since(&self, product_id: &str, since: DateTime) {
+        debug!("Conduit: trades_since sent...");
+        let msg = self.fetch_trades_since(product_id, since).await.map(Message::Trades);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_trades_since(&self, product_id: &str, since: DateTime) -> Result<Vec<Trade>, CBProError> {
+        self._fetch_trades(product_id, |t| t.time > since).await
+    }
+
+    async fn _fetch_trades<F>(&self, product_id: &str, newer: F) -> Result<Vec<Trade>, CBProError>
+        where F: Fn(&Trade) -> bool {
+        let path = format!("/products/{}/trades", product_id);
+        let mut trades: Vec<Trade> = vec!();
+        let mut after: Option<String> = None;
+        loop {
+            let page = self._request_page::<Trade>(&path, &[], after.take()).await?;
+            let (mut newer_trades, more) = _newer_trades(page.items, &newer, page.after.is_some());
+            trades.append(&mut newer_trades);
+            if !more {
+                break;
+            }
+            after = page.after;
+        }
+        trades.reverse();
+        Ok(trades)
+    }
 
     /// **Core Requests**
     ///
     ///
     ///
-    pub async fn heartbeat(&mut self) {
+    pub async fn heartbeat(&self, product_ids: Vec<String>) {
         debug!("Conduit: heartbeat...");
         self.subscribe(&[WSChannel::WithProduct {
             name: WSChannelType::Heartbeat,
-            product_ids: vec!("BTC-USD".to_string())
+            product_ids
         }]
         ).await
     }
 
-    pub async fn level(&mut self, level: Level) {
+    /// Subscribes to the book feed for `product_ids`.  Feed `book::l2::OrderBooks` (one book
+    /// per product) with the mailbox to follow Level2 books.  Level3 is the `full` channel:
+    /// every order event arrives as a `Message::WSLevel3`.  There is no Level1 feed (it's
+    /// only a REST `order_book` level); use `ticker` for the best bid/ask.
+    pub async fn level(&self, level: Level, product_ids: Vec<String>) {
         debug!("Conduit: level...");
         match level {
+            Level::Level1 => {
+                let e = CBProError::Message("no Level1 feed: use `ticker` for the best bid/ask".to_string());
+                self._send(Err(e)).await
+            },
             Level::Level2 => self.subscribe(
                         &[WSChannel::WithProduct {
                                 name: WSChannelType::Level2,
-                                product_ids: vec!("BTC-USD".to_string())
+                                product_ids
+                                }]
+                            ).await,
+            Level::Level3 => self.subscribe(
+                        &[WSChannel::WithProduct {
+                                name: WSChannelType::Full,
+                                product_ids
                                 }]
                             ).await,
-            Level::Level3 => {
-                panic!("NOT IMPLEMENTED...");
-                self.subscribe(
-                    &[WSChannel::WithProduct {
-                        name: WSChannelType::Full,
-                        product_ids: vec!("BTC-USD".to_string())
-                    }]
-                ).await;
-            }
         };
     }
 
-    pub async fn order_book(&mut self, level: Level) {
+    /// **Order Book**
+    ///
+    /// A REST snapshot of `product_id`'s book, delivered as a `Message::Book` whose
+    /// `sequence` can be used to seed a book from the WS feed.
+    pub async fn order_book(&self, product_id: &str, level: Level) {
         debug!("Conduit: order_book sent...");
-        self._get("products", "/products").await;
+        let msg = self.fetch_order_book(product_id, level).await
+            .map(|book| Message::Book { product_id: product_id.to_string(), book });
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_order_book(&self, product_id: &str, level: Level) -> Result<book::BookSnapshot, CBProError> {
+        match level {
+            Level::Level1 => self._book(product_id).await.map(book::BookSnapshot::Level1),
+            Level::Level2 => self._book(product_id).await.map(book::BookSnapshot::Level2),
+            Level::Level3 => self._book(product_id).await.map(book::BookSnapshot::Level3),
+        }
+    }
+
+    async fn _book<T>(&self, product_id: &str) -> Result<book::Book<T>, CBProError>
+        where T: book::BookLevel,
+              for<'de> T: serde::Deserialize<'de> {
+        self._get(&format!("/products/{}/book?level={}", product_id, T::level())).await
+    }
+
+    /// **Market Data**
+    ///
+    /// A REST snapshot of the last trade and best bid/ask; `ticker` is the live (WS) version.
+    pub async fn product_ticker(&self, product_id: &str) {
+        debug!("Conduit: product_ticker sent...");
+        let msg = self.fetch_product_ticker(product_id).await
+            .map(|ticker| Message::ProductTicker { product_id: product_id.to_string(), ticker });
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_product_ticker(&self, product_id: &str) -> Result<ProductTicker, CBProError> {
+        self._get(&format!("/products/{}/ticker", product_id)).await
+    }
+
+    pub async fn stats24h(&self, product_id: &str) {
+        debug!("Conduit: stats24h sent...");
+        let msg = self.fetch_stats24h(product_id).await
+            .map(|stats| Message::Stats24H { product_id: product_id.to_string(), stats });
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_stats24h(&self, product_id: &str) -> Result<Stats24H, CBProError> {
+        self._get(&format!("/products/{}/stats", product_id)).await
     }
 
-    pub async fn products(&mut self) {
+    pub async fn currencies(&self) {
+        debug!("Conduit: currencies sent...");
+        let msg = self.fetch_currencies().await.map(Message::Currencies);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_currencies(&self) -> Result<Vec<Currency>, CBProError> {
+        self._get("/currencies").await
+    }
+
+    pub async fn products(&self) {
         debug!("Conduit: products sent...");
-        self._get("products", "/products").await;
+        let msg = self.fetch_products().await.map(Message::Products);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_products(&self) -> Result<Vec<Product>, CBProError> {
+        self._get("/products").await
     }
 
-    pub async fn status(&mut self) {
+    pub async fn status(&self) {
         debug!("Conduit: status...");
         self.subscribe(&[WSChannel::Name(WSChannelType::Status)]).await;
     }
 
-    pub async fn ticker(&mut self, product_ids: Vec<String>) {
+    pub async fn ticker(&self, product_ids: Vec<String>) {
         debug!("Conduit: ticker...");
         self.subscribe(
             &[WSChannel::WithProduct { name: WSChannelType::Ticker, product_ids }]
         ).await;
     }
 
-    pub async fn time(&mut self) {
+    pub async fn time(&self) {
         debug!("Conduit: time sent...");
-        self._get("time", "/time").await;
+        let msg = self.fetch_time().await.map(Message::Time);
+        self._send(msg).await;
+    }
+
+    pub async fn fetch_time(&self) -> Result<Time, CBProError> {
+        self._get("/time").await
     }
-    pub fn interval(&mut self, millis: u64) {
+
+    pub fn interval(&self, millis: u64) {
         let to_mailbox = self.to_mailbox.clone();
         task::spawn((|| async move{
             interval(Duration::from_millis(millis)).for_each(|_| async {
@@ -233,67 +964,351 @@ impl Conduit<'static> {
 //////////////////////////////////////////////////////////////////////////
 // Websocket
 //////////////////////////////////////////////////////////////////////////
-fn handle_websocket(ws_uri:      &'static str,
-                    to_mailbox:  Arc<Mutex<Sender<Message>>>,
-                    inbox:       Arc<Mutex<Receiver<Message>>>) {
-    task::spawn((|| async move{
-        let (_ws, _) = connect_async(ws_uri)
-            .await
-            .expect("Failed on WS connect");
-        debug!("Conduit: WebSocket handshake has been successfully completed...");
-        let (mut _ws_write, mut _ws_read) = _ws.split();
-        let ws_write = Arc::new(Mutex::new(_ws_write));
-        let ws_read = Arc::new(Mutex::new(_ws_read));
+fn handle_websocket(ws_uri:          String,
+                    authenticator:   Arc<Authenticator>,
+                    subscriptions:   Arc<Mutex<Subscriptions>>,
+                    reconnect_policy: RetryPolicy,
+                    connect_timeout: Duration,
+                    to_mailbox:      Arc<Mutex<Sender<Message>>>,
+                    inbox:           Receiver<Message>) {
+    task::spawn(async move {
+        // Consecutive failures: failed connects, and connections which dropped before proving
+        // healthy (e.g. Coinbase accepting the handshake and closing straight away).
+        let mut attempt = 0;
         loop {
-            futures::future::select(
-                // Incoming
-                (|| async {
-                    let tungstenite_msg = ws_read.lock().await.next().await;
-                    let conduit_msg = match tungstenite_msg.unwrap() {
-                        Ok(TMessage::Text(msg)) => {
-                            trace!("handle_incoming: {:?}\n", &msg);
-                            serde_json::from_str(&msg).unwrap_or_else(|e| {
-                                warn!("Can't decode: {:?}", msg);
-                                Message::InternalError(CBProError::Serde(e.to_string()))
-                            })},
-                        o => {
-                            debug!("Tungstenite unwrap error: {:?}\n", o);
-                            Message::None
-                        }
-                    };
-                    match &conduit_msg {
-                        Message::InternalError(e) => {
-                            warn!("InternalError: {:?}", e)
+            if attempt > 0 {
+                let delay = reconnect_policy.delay(attempt);
+                debug!("Conduit: WebSocket reconnecting in {:?} (attempt {})...", delay, attempt);
+                task::sleep(delay).await;
+            }
+            let connected = match async_std::future::timeout(connect_timeout, connect_async(ws_uri.as_str())).await {
+                Ok(Ok((ws, _))) => Ok(ws),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err(format!("connect timed out after {:?}", connect_timeout))
+            };
+            let reason = match connected {
+                Err(reason) => {
+                    warn!("Conduit: WebSocket connect failed: {}", reason);
+                    reason
+                },
+                Ok(ws) => {
+                    debug!("Conduit: WebSocket handshake has been successfully completed...");
+                    let connected_at = std::time::Instant::now();
+                    let (mut ws_write, mut ws_read) = ws.split();
+                    let reason = match _resubscribe(&mut ws_write, &authenticator, &subscriptions, &to_mailbox).await {
+                        Err(reason) => {
+                            warn!("Conduit: WebSocket dropped while resubscribing: {}", reason);
+                            reason
                         },
-                        _ => {
-                            to_mailbox.lock().await.send(conduit_msg).await
+                        Ok(replayed) => {
+                            to_mailbox.lock().await.send(Message::WSConnected).await;
+                            match _pump_websocket(&mut ws_write, &mut ws_read, &inbox, &to_mailbox, &subscriptions, replayed).await {
+                                Some(reason) => {
+                                    warn!("Conduit: WebSocket dropped: {}", reason);
+                                    reason
+                                },
+                                None => {
+                                    debug!("Conduit: dropped, closing the WebSocket...");
+                                    let _ = ws_write.close().await;
+                                    return;
+                                }
+                            }
                         }
+                    };
+                    subscriptions.lock().await.disconnected();
+                    to_mailbox.lock().await.send(Message::WSDisconnected { reason: reason.clone() }).await;
+                    if connected_at.elapsed() >= HEALTHY_UPTIME {
+                        attempt = 0;
                     }
-                })().boxed(),
-                // Outgoing
-                (|| async {
-                    match Arc::clone(&inbox).lock().await.recv().await {
-                        Ok(msg) => {
-                            debug!("handle_outgoing: {:?}\n", &msg);
-                            let smsg = serde_json::to_string(&msg).unwrap();
-                            match ws_write.lock().await.send(TMessage::Text(smsg)).await {
-                                Ok(v)  => {println!("OK {:?}", v);},
-                                Err(e) => {println!("ERR {:?}", e);}
-                            };
-                        },
-                        Err(e) => {
-                            error!("handle_outgoing: Error: {:?}\n", e.to_string());
+                    reason
+                }
+            };
+            attempt += 1;
+            if attempt >= reconnect_policy.max_attempts {
+                error!("Conduit: WebSocket giving up after {} attempts", attempt);
+                let reason = format!("gave up reconnecting: {}", reason);
+                to_mailbox.lock().await.send(Message::WSDisconnected { reason }).await;
+                return;
+            }
+        }
+    });
+}
+
+/// Replays every channel subscribed to on a new connection, returning what was replayed.
+async fn _resubscribe<W>(ws_write:      &mut W,
+                         authenticator: &Arc<Authenticator>,
+                         subscriptions: &Arc<Mutex<Subscriptions>>,
+                         to_mailbox:    &Arc<Mutex<Sender<Message>>>) -> Result<Subscriptions, String>
+    where W: futures::Sink<TMessage> + Unpin + Send,
+          W::Error: std::fmt::Debug {
+    let channels = subscriptions.lock().await.requested();
+    let mut replayed = Subscriptions::new();
+    if channels.is_empty() {
+        return Ok(replayed);
+    }
+    replayed.subscribe(&channels);
+    // The original signatures have expired...
+    let auth = match authenticator.ws_auth().await {
+        Ok(auth) => auth,
+        Err(e) => {
+            to_mailbox.lock().await.send(Message::InternalError(e)).await;
+            None
+        }
+    };
+    let msg = Message::WSSubscribe(WSSubscribe { channels, auth });
+    debug!("Conduit: WebSocket resubscribing: {:?}", &msg);
+    let smsg = serde_json::to_string(&msg).unwrap();
+    ws_write.send(TMessage::Text(smsg)).await.map_err(|e| format!("{:?}", e))?;
+    Ok(replayed)
+}
+
+/// Shuttles messages between the WebSocket and the Conduit until the socket drops (returning
+/// why) or the Conduit is dropped (returning `None`).
+///
+/// `sent` is what this connection has been asked for so far (starting with the replay):
+/// queued subscriptions which it already covers are dropped rather than sent twice (and
+/// possibly with a signature which expired while they were queued).
+async fn _pump_websocket<W, R>(ws_write:   &mut W,
+                               ws_read:    &mut R,
+                               inbox:      &Receiver<Message>,
+                               to_mailbox: &Arc<Mutex<Sender<Message>>>,
+                               subscriptions: &Arc<Mutex<Subscriptions>>,
+                               mut sent:   Subscriptions) -> Option<String>
+    where W: futures::Sink<TMessage> + Unpin + Send,
+          W::Error: std::fmt::Debug,
+          R: futures::Stream<Item = Result<TMessage, async_tungstenite::tungstenite::Error>> + Unpin + Send {
+    loop {
+        match futures::future::select(ws_read.next().boxed(), inbox.recv().boxed()).await {
+            // Incoming
+            futures::future::Either::Left((tungstenite_msg, _)) => {
+                let conduit_msg = match tungstenite_msg {
+                    Some(Ok(TMessage::Text(msg))) => {
+                        trace!("handle_incoming: {:?}\n", &msg);
+                        Message::from_ws_text(&msg).unwrap_or_else(|e| {
+                            warn!("Can't decode: {:?}", msg);
+                            Message::InternalError(CBProError::Serde(e.to_string()))
+                        })},
+                    Some(Ok(TMessage::Close(frame))) => return Some(format!("closed by Coinbase: {:?}", frame)),
+                    Some(Err(e)) => return Some(e.to_string()),
+                    None => return Some("connection closed".to_string()),
+                    o => {
+                        trace!("Tungstenite: {:?}\n", o);
+                        Message::None
+                    }
+                };
+                match &conduit_msg {
+                    Message::InternalError(e) => {
+                        warn!("InternalError: {:?}", e)
+                    },
+                    Message::WSSubscriptions { channels } => {
+                        let mut subscriptions = subscriptions.lock().await;
+                        subscriptions.confirm(channels);
+                        if !subscriptions.is_reconciled() {
+                            debug!("Conduit: subscriptions pending: {:?}, unexpected: {:?}",
+                                   subscriptions.pending(), subscriptions.unexpected());
                         }
+                        drop(subscriptions);
+                        to_mailbox.lock().await.send(conduit_msg).await
+                    },
+                    _ => {
+                        to_mailbox.lock().await.send(conduit_msg).await
                     }
-                })().boxed()
-            ).await;
+                }
+            },
+            // Outgoing
+            futures::future::Either::Right((Ok(msg), _)) => {
+                debug!("handle_outgoing: {:?}\n", &msg);
+                match &msg {
+                    Message::WSSubscribe(subscribe) if sent.covers(&subscribe.channels) => {
+                        debug!("Conduit: already subscribed, dropping: {:?}", &msg);
+                        continue;
+                    },
+                    Message::WSSubscribe(subscribe) => sent.subscribe(&subscribe.channels),
+                    Message::WSUnsubscribe(unsubscribe) => sent.unsubscribe(&unsubscribe.channels),
+                    _ => {}
+                }
+                let smsg = serde_json::to_string(&msg).unwrap();
+                if let Err(e) = ws_write.send(TMessage::Text(smsg)).await {
+                    // The subscription is replayed after reconnecting.
+                    return Some(format!("{:?}", e));
+                }
+            },
+            // The Conduit has been dropped.
+            futures::future::Either::Right((Err(_), _)) => return None
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+// Clock
+//////////////////////////////////////////////////////////////////////////
+/// Stops once the `Conduit` (and its WebSocket task) are gone, i.e. once nobody reads `skew`.
+fn handle_clock(http_uri: String, user_agent: String, period: Duration, timeout: Duration, skew: Weak<AtomicI64>) {
+    task::spawn((|| async move{
+        loop {
+            task::sleep(period).await;
+            if skew.strong_count() == 0 {
+                debug!("Conduit: clock task stopping...");
+                break;
+            }
+            let measured = _measure_skew(&http_uri, &user_agent, timeout).await;
+            let skew = match skew.upgrade() {
+                Some(skew) => skew,
+                None => break
+            };
+            match measured {
+                Ok(ms) => {
+                    debug!("Conduit: clock skew: {}ms", ms);
+                    skew.store(ms, Ordering::SeqCst)
+                },
+                Err(e) => warn!("Conduit: can't measure clock skew: {:?}", e)
+            }
         }
     })());
 }
 
+/// Measures Coinbase's clock minus ours (in millis) against `/time`, assuming that the
+/// server read its clock halfway through the round trip.
+async fn _measure_skew(http_uri: &str, user_agent: &str, timeout: Duration) -> Result<i64, CBProError> {
+    let before = _timestamp_millis();
+    let req = surf::get(format!("{}/time", http_uri))
+        .set_header("User-Agent", user_agent)
+        .recv_json::<Time>();
+    let time = async_std::future::timeout(timeout, req).await
+        .map_err(|_| CBProError::Http(format!("timed out after {:?}", timeout)))?
+        .map_err(|e| CBProError::Http(e.to_string()))?;
+    let after = _timestamp_millis();
+    Ok((time.epoch * 1000.0) as i64 - ((before + after) / 2) as i64)
+}
+
 //////////////////////////////////////////////////////////////////////////
 // HELPERS
 //////////////////////////////////////////////////////////////////////////
+/// What's needed to authenticate: the API key and passphrase, and whatever holds the secret.
+struct Identity {
+    key: String,
+    passphrase: Secret,
+    signer: Arc<dyn Signer>,
+}
+
+/// Signs requests.  Shared with the WebSocket task, which re-signs its subscriptions
+/// after reconnecting.
+struct Authenticator {
+    identity: Option<Identity>,
+    // Coinbase's clock minus ours, in millis.  Applied to signature timestamps.
+    skew: Arc<AtomicI64>,
+}
+
+impl Authenticator {
+    fn identity(&self, method: surf::http_types::Method, uri: &str) -> Result<&Identity, CBProError> {
+        self.identity.as_ref()
+            .ok_or_else(|| CBProError::Credentials(format!("{} {} requires credentials", method, uri)))
+    }
+
+    async fn sign(&self, method: surf::http_types::Method, uri: &str, body: Option<serde_json::Value>)
+                  -> Result<(String, u64), CBProError> {
+        let ts = ((_timestamp_millis() as i64 + self.skew.load(Ordering::SeqCst)) / 1000) as u64;
+        let identity = self.identity(method, uri)?;
+        // Bodyless requests (GET, DELETE) sign an empty body.
+        let unwrapped_body: String = match body {
+            None => String::from(""),
+            Some(v) => v.to_string()
+        };
+        let message = ts.to_string() + method.as_ref() + uri + unwrapped_body.as_str();
+        Ok((identity.signer.sign(&message).await?, ts))
+    }
+
+    async fn auth(&self, method: surf::http_types::Method, path: &str, body: Option<serde_json::Value>)
+                  -> Result<Auth, CBProError> {
+        debug!("Conduit: calculating auth...");
+        let (signature, ts) = self.sign(method, path, body).await?;
+        // `sign` has checked that there is an identity...
+        let identity = self.identity.as_ref().unwrap();
+        Ok(Auth {
+            signature: signature,
+            key: identity.key.to_string(),
+            passphrase: identity.passphrase.clone(),
+            timestamp: ts.to_string()
+        })
+    }
+
+    /// The auth for a WS subscription, if there are credentials.
+    async fn ws_auth(&self) -> Result<Option<Auth>, CBProError> {
+        match &self.identity {
+            Some(_) => self.auth(surf::http_types::Method::Get, "/users/self/verify", None).await.map(Some),
+            None => {
+                debug!("Conduit: **not** calculating auth... ");
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// A REST response body along with its pagination cursors.
+struct Response {
+    before: Option<String>,
+    after: Option<String>,
+    body: String,
+}
+
+/// Splits `[start, end]` into the windows of at most `MAX_CANDLES` candles which Coinbase
+/// will serve.  Windows share their boundaries; the duplicate candles are dropped later.
+fn _candle_windows(start: DateTime, end: DateTime, granularity: Granularity) -> Vec<(DateTime, DateTime)> {
+    let step = chrono::Duration::seconds(granularity as i64 * MAX_CANDLES);
+    let mut windows = vec!();
+    let mut from = start;
+    while from < end {
+        let to = std::cmp::min(from + step, end);
+        windows.push((from, to));
+        from = to;
+    }
+    windows
+}
+
+/// Keeps the leading (i.e. newest) trades of a page for which `newer` holds, and whether
+/// the next (older) page may hold more: the cutoff wasn't reached and there is a next page.
+fn _newer_trades<F>(page: Vec<Trade>, newer: F, has_next: bool) -> (Vec<Trade>, bool)
+    where F: Fn(&Trade) -> bool {
+    let len = page.len();
+    let trades: Vec<Trade> = page.into_iter().take_while(|t| newer(t)).collect();
+    let more = has_next && len > 0 && trades.len() == len;
+    (trades, more)
+}
+
+/// Why a REST attempt failed and, for 429s, when Coinbase says to retry.
+struct Failure {
+    error: CBProError,
+    retry_after: Option<Duration>,
+}
+
+impl From<CBProError> for Failure {
+    fn from(error: CBProError) -> Self {
+        Self { error, retry_after: None }
+    }
+}
+
+/// Whether `path` (which may carry a query string) is a private endpoint.
+fn _is_private(path: &str) -> bool {
+    PRIVATE_PATHS.iter().any(|p| {
+        path.starts_with(p) && matches!(path[p.len()..].chars().next(), None | Some('/') | Some('?'))
+    })
+}
+
+/// Builds a query string from the params which are set, e.g. `?status=open&after=123`.
+fn _query(params: &[(&str, Option<String>)]) -> String {
+    let query = params.iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| {
+            format!("{}={}", k, url::form_urlencoded::byte_serialize(v.as_bytes()).collect::<String>())
+        }))
+        .collect::<Vec<_>>()
+        .join("&");
+    if query.is_empty() {
+        query
+    } else {
+        format!("?{}", query)
+    }
+}
+
 fn _timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -301,3 +1316,94 @@ fn _timestamp() -> u64 {
         .as_secs()
 }
 
+fn _timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("leap-second")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_private() {
+        assert!(_is_private("/accounts"));
+        assert!(_is_private("/orders?status=open"));
+        assert!(_is_private("/orders/client:2fec40ac-525b-4192-871a-39d784945055"));
+        assert!(!_is_private("/products/BTC-USD/book?level=2"));
+        assert!(!_is_private("/time"));
+        assert!(!_is_private("/ordersbook"));
+    }
+
+    #[test]
+    fn test_candle_windows() {
+        let start: DateTime = "2020-01-01T00:00:00Z".parse().unwrap();
+        let end: DateTime = "2020-01-02T00:00:00Z".parse().unwrap();
+
+        // 1440 minutes => 300 + 300 + 300 + 300 + 240
+        let windows = _candle_windows(start, end, Granularity::M1);
+        assert_eq!(windows.len(), 5);
+        assert_eq!(windows[0].0, start);
+        assert_eq!(windows[4].1, end);
+        assert!(windows.windows(2).all(|w| w[0].1 == w[1].0));
+
+        assert_eq!(_candle_windows(start, end, Granularity::H1).len(), 1);
+        assert!(_candle_windows(end, start, Granularity::H1).is_empty());
+    }
+
+    fn trades(ids: &[usize]) -> Vec<Trade> {
+        ids.iter()
+            .map(|&trade_id| Trade {
+                time: "2020-01-01T00:00:00Z".parse().unwrap(),
+                trade_id,
+                price: 7000.0,
+                size: 0.1,
+                side: OrderSide::Buy,
+            })
+            .collect()
+    }
+
+    fn ids(trades: &[Trade]) -> Vec<usize> {
+        trades.iter().map(|t| t.trade_id).collect()
+    }
+
+    #[test]
+    fn test_newer_trades() {
+        let newer = |t: &Trade| t.trade_id > 100;
+
+        // The cutoff falls mid-page: keep the newer ones and stop.
+        let (kept, more) = _newer_trades(trades(&[103, 102, 101, 100, 99]), newer, true);
+        assert_eq!(ids(&kept), vec!(103, 102, 101));
+        assert!(!more);
+
+        // The whole page is newer: the cutoff may be on the next page.
+        let (kept, more) = _newer_trades(trades(&[103, 102, 101]), newer, true);
+        assert_eq!(ids(&kept), vec!(103, 102, 101));
+        assert!(more);
+
+        // ...which starts exactly at the cutoff.
+        let (kept, more) = _newer_trades(trades(&[100, 99, 98]), newer, true);
+        assert!(kept.is_empty());
+        assert!(!more);
+
+        // No newer trades at all, an empty page, or no next page.
+        let (kept, more) = _newer_trades(trades(&[90, 89]), newer, true);
+        assert!(kept.is_empty());
+        assert!(!more);
+        assert_eq!(_newer_trades(trades(&[]), newer, true), (vec!(), false));
+        let (kept, more) = _newer_trades(trades(&[102, 101]), newer, false);
+        assert_eq!(ids(&kept), vec!(102, 101));
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(_query(&[("a", None)]), "");
+        assert_eq!(_query(&[("currency", Some("XRP".to_string())),
+                            ("type", None),
+                            ("crypto_address", Some("r9cZ A&b=c".to_string()))]),
+                   "?currency=XRP&crypto_address=r9cZ+A%26b%3Dc");
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use crate::limiter::RateLimit;
+use crate::retry::RetryPolicy;
+use crate::{MAIN_URL, SANDBOX_URL, WS_SANDBOX_URL, WS_URL};
+
+/// Everything needed to build a `Conduit`.  Start from a preset and override as needed:
+///
+/// ```ignore
+/// let config = ConduitConfig::sandbox()
+///     .mailbox_capacity(1024)
+///     .user_agent_suffix("treasury");
+/// let (conduit, mailbox) = Conduit::new(config, None).await;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConduitConfig {
+    pub http_url: String,
+    pub ws_url: String,
+    /// How many messages the mailbox (and the WS outbox) buffer; at least 1.
+    pub mailbox_capacity: usize,
+    /// How long a single REST attempt (or WebSocket connect) may take.
+    pub request_timeout: Duration,
+    /// How often the clock skew against Coinbase is re-measured.
+    pub clock_sync_interval: Duration,
+    /// Appended to the `User-Agent` header, e.g. to identify a strategy.
+    pub user_agent_suffix: Option<String>,
+    pub public_rate_limit: RateLimit,
+    pub private_rate_limit: RateLimit,
+    pub retry_policy: RetryPolicy,
+    /// How the WebSocket reconnects after it drops.  `max_attempts` counts consecutive
+    /// failures (failed connects, or connections dropped within 30s) before giving up.
+    pub reconnect_policy: RetryPolicy,
+}
+
+impl ConduitConfig {
+    pub fn new<H: Into<String>, W: Into<String>>(http_url: H, ws_url: W) -> Self {
+        Self {
+            http_url: http_url.into(),
+            ws_url: ws_url.into(),
+            mailbox_capacity: 128,
+            request_timeout: Duration::from_secs(30),
+            clock_sync_interval: Duration::from_secs(300),
+            user_agent_suffix: None,
+            public_rate_limit: RateLimit::PUBLIC,
+            private_rate_limit: RateLimit::PRIVATE,
+            retry_policy: RetryPolicy::default(),
+            reconnect_policy: RetryPolicy {
+                max_attempts: u32::MAX,
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(60),
+            },
+        }
+    }
+
+    pub fn production() -> Self {
+        Self::new(MAIN_URL, WS_URL)
+    }
+
+    pub fn sandbox() -> Self {
+        Self::new(SANDBOX_URL, WS_SANDBOX_URL)
+    }
+
+    pub fn http_url<S: Into<String>>(self, http_url: S) -> Self {
+        Self { http_url: http_url.into(), ..self }
+    }
+
+    pub fn ws_url<S: Into<String>>(self, ws_url: S) -> Self {
+        Self { ws_url: ws_url.into(), ..self }
+    }
+
+    /// Clamped to at least 1: the channels can't be unbuffered.
+    pub fn mailbox_capacity(self, mailbox_capacity: usize) -> Self {
+        Self { mailbox_capacity: mailbox_capacity.max(1), ..self }
+    }
+
+    pub fn request_timeout(self, request_timeout: Duration) -> Self {
+        Self { request_timeout, ..self }
+    }
+
+    pub fn clock_sync_interval(self, clock_sync_interval: Duration) -> Self {
+        Self { clock_sync_interval, ..self }
+    }
+
+    pub fn user_agent_suffix<S: Into<String>>(self, suffix: S) -> Self {
+        Self { user_agent_suffix: Some(suffix.into()), ..self }
+    }
+
+    pub fn rate_limits(self, public_rate_limit: RateLimit, private_rate_limit: RateLimit) -> Self {
+        Self { public_rate_limit, private_rate_limit, ..self }
+    }
+
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self { retry_policy, ..self }
+    }
+
+    pub fn reconnect_policy(self, reconnect_policy: RetryPolicy) -> Self {
+        Self { reconnect_policy, ..self }
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::{ env, fmt, fs::File, io::BufReader, path::PathBuf };
+
+use crate::errors::CBProError;
+use crate::structs::Credentials;
+
+/// A string which `Debug` (and so logging) never prints.
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new<S: Into<String>>(secret: S) -> Self {
+        Secret(secret.into())
+    }
+
+    /// The secret itself.  Don't log it...
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Secret(secret)
+    }
+}
+
+/// A source of `Credentials` (env vars, a file, a vault...).  Implementations should
+/// `validate` what they load so that bad credentials fail before the first request.
+pub trait CredentialProvider {
+    fn credentials(&self) -> Result<Credentials, CBProError>;
+}
+
+/// Reads `COINBASE_PRO_KEY`, `COINBASE_PRO_SECRET` and `COINBASE_PRO_PASSPHRASE`
+/// (or the same names under another prefix).
+#[derive(Clone, Debug)]
+pub struct EnvCredentials {
+    prefix: String,
+}
+
+impl EnvCredentials {
+    pub fn new() -> Self {
+        Self::with_prefix("COINBASE_PRO")
+    }
+
+    pub fn with_prefix<S: Into<String>>(prefix: S) -> Self {
+        Self { prefix: prefix.into() }
+    }
+
+    fn var(&self, name: &str) -> Result<String, CBProError> {
+        let var = format!("{}_{}", self.prefix, name);
+        env::var(&var).map_err(|e| CBProError::Credentials(format!("{}: {}", var, e)))
+    }
+}
+
+impl Default for EnvCredentials {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self) -> Result<Credentials, CBProError> {
+        let credentials = Credentials {
+            key: self.var("KEY")?,
+            secret: self.var("SECRET")?.into(),
+            passphrase: self.var("PASSPHRASE")?.into(),
+        };
+        credentials.validate()?;
+        Ok(credentials)
+    }
+}
+
+/// Reads a JSON file shaped like `credentials.json.template`.
+#[derive(Clone, Debug)]
+pub struct FileCredentials {
+    path: PathBuf,
+}
+
+impl FileCredentials {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CredentialProvider for FileCredentials {
+    fn credentials(&self) -> Result<Credentials, CBProError> {
+        let file = File::open(&self.path)
+            .map_err(|e| CBProError::Credentials(format!("{:?}: {}", self.path, e)))?;
+        let credentials: Credentials = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| CBProError::Credentials(format!("{:?}: {}", self.path, e)))?;
+        credentials.validate()?;
+        Ok(credentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_redacted() {
+        let credentials = Credentials {
+            key: "key".to_string(),
+            secret: Secret::new("c2VjcmV0"),
+            passphrase: Secret::new("hunter2"),
+        };
+        let debug = format!("{:?}", credentials);
+        assert!(!debug.contains("c2VjcmV0"));
+        assert!(!debug.contains("hunter2"));
+        assert!(credentials.validate().is_ok());
+
+        let credentials = Credentials { secret: Secret::new("not base64!"), ..credentials };
+        assert!(credentials.validate().is_err());
+    }
+}
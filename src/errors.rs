@@ -1,3 +1,4 @@
+use serde::Deserialize;
 use std::error::Error;
 use std::fmt;
 
@@ -5,6 +6,7 @@ use std::fmt;
 pub enum CBProError {
     Coinbase(CBError),
     Connect(String),
+    Credentials(String),
     Http(String),
     Message(String),
     Null,
@@ -13,6 +15,17 @@ pub enum CBProError {
     Serde(String ),
 }
 
+impl CBProError {
+    /// Whether the failure is transient (connection trouble, 429s and 5xxs).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CBProError::Coinbase(e) => e.is_retryable(),
+            CBProError::Http(_) | CBProError::Read(_) => true,
+            _ => false
+        }
+    }
+}
+
 impl fmt::Display for CBProError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -21,14 +34,56 @@ impl fmt::Display for CBProError {
 
 impl Error for CBProError {}
 
+/// A REST request which Coinbase (or something in front of it) rejected.
 #[derive(Debug, PartialOrd, PartialEq)]
-pub struct CBError(String);
+pub struct CBError {
+    pub status: u16,
+    /// Coinbase's explanation (e.g. "Insufficient funds") or, failing that, the raw body.
+    pub message: String,
+    pub path: String,
+}
+
+impl CBError {
+    pub fn new(status: u16, path: &str, body: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Rejection {
+            message: String,
+        }
+
+        let message = serde_json::from_str::<Rejection>(body)
+            .map(|r| r.message)
+            .unwrap_or_else(|_| body.to_string());
+        Self { status, message, path: path.to_string() }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.status == 429 || self.status >= 500
+    }
+}
 
 impl Error for CBError {}
 
 impl fmt::Display for CBError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{} {}: {}", self.status, self.path, self.message)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cberror() {
+        let e = CBError::new(400, "/orders", r#"{"message": "Insufficient funds"}"#);
+        assert_eq!(e.message, "Insufficient funds");
+        assert!(!CBProError::Coinbase(e).is_retryable());
+
+        let e = CBError::new(502, "/orders", "<html>Bad Gateway</html>");
+        assert_eq!(e.message, "<html>Bad Gateway</html>");
+        assert!(CBProError::Coinbase(e).is_retryable());
+
+        assert!(CBError::new(429, "/time", "").is_retryable());
+        assert!(!CBProError::Serde("".to_string()).is_retryable());
+    }
+}
@@ -21,8 +21,14 @@ extern crate url;
 
 pub mod book;
 pub mod conduit;
+pub mod config;
+pub mod credentials;
 pub mod errors;
+pub mod limiter;
+pub mod retry;
+pub mod signer;
 pub mod structs;
+pub mod subscriptions;
 mod utils;
 
 pub const MAIN_URL: &str = "https://api.pro.coinbase.com";
@@ -0,0 +1,160 @@
+use async_std::{ sync::Mutex, task };
+use std::{ sync::atomic::{ AtomicUsize, Ordering },
+           time::{ Duration, Instant }};
+
+/// A token bucket's budget: `rate` requests per second, bursting up to `burst`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: f64,
+}
+
+impl RateLimit {
+    /// Coinbase's published limit for public endpoints.
+    pub const PUBLIC: RateLimit = RateLimit { rate: 3.0, burst: 6.0 };
+    /// Coinbase's published limit for private endpoints.
+    pub const PRIVATE: RateLimit = RateLimit { rate: 5.0, burst: 10.0 };
+
+    /// The slowest rate a `Limiter` accepts: one request every 100s.
+    pub const MIN_RATE: f64 = 0.01;
+
+    /// Clamped so that a bucket always refills (`rate`) and can hold a whole token (`burst`).
+    fn clamped(self) -> Self {
+        RateLimit { rate: self.rate.max(Self::MIN_RATE), burst: self.burst.max(1.0) }
+    }
+}
+
+/// The number of requests waiting on each bucket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueueDepth {
+    pub public: usize,
+    pub private: usize,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Self { limit, tokens: limit.burst, last: Instant::now() }
+    }
+
+    /// Takes a token if one is available, otherwise says how long until one will be.
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.limit.rate))
+        }
+    }
+}
+
+/// Queues requests (rather than failing them) until their bucket has a token.
+#[derive(Debug)]
+pub struct Limiter {
+    bucket: Mutex<Bucket>,
+    queued: AtomicUsize,
+}
+
+impl Limiter {
+    /// `limit` is clamped to at least `RateLimit::MIN_RATE` and a burst of 1.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket::new(limit.clamped())),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        // Dequeued on the way out, even if the caller gives up (drops this future) mid-wait.
+        let _queued = Queued(&self.queued);
+        loop {
+            let wait = self.bucket.lock().await.take(Instant::now());
+            match wait {
+                None => break,
+                Some(d) => {
+                    trace!("Limiter: waiting {:?}...", d);
+                    task::sleep(d).await
+                }
+            }
+        }
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+}
+
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_burst_and_refill() {
+        let mut bucket = Bucket::new(RateLimit { rate: 2.0, burst: 3.0 });
+        let start = bucket.last;
+
+        // The full burst is available immediately...
+        assert!((0..3).all(|_| bucket.take(start).is_none()));
+        // ...then callers wait for the refill.
+        assert_eq!(bucket.take(start), Some(Duration::from_millis(500)));
+        assert!(bucket.take(start + Duration::from_millis(500)).is_none());
+
+        // Idle time never banks more than the burst.
+        let later = start + Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.take(later).is_none()));
+        assert!(bucket.take(later).is_some());
+    }
+
+    #[test]
+    fn test_queued() {
+        let limiter = std::sync::Arc::new(Limiter::new(RateLimit { rate: 5.0, burst: 1.0 }));
+        task::block_on(async {
+            // The first request takes the only token; the other two queue behind it.
+            let requests: Vec<_> = (0..3)
+                .map(|_| {
+                    let limiter = limiter.clone();
+                    task::spawn(async move { limiter.acquire().await })
+                })
+                .collect();
+            task::sleep(Duration::from_millis(50)).await;
+            assert_eq!(limiter.queued(), 2);
+
+            for request in requests {
+                request.await;
+            }
+            assert_eq!(limiter.queued(), 0);
+
+            // A caller which gives up while queued doesn't stay counted.
+            let gave_up = async_std::future::timeout(Duration::from_millis(50), limiter.acquire()).await;
+            assert!(gave_up.is_err());
+            assert_eq!(limiter.queued(), 0);
+        });
+    }
+
+    #[test]
+    fn test_clamped_limit() {
+        let limiter = Limiter::new(RateLimit { rate: 0.0, burst: 0.0 });
+        let mut bucket = task::block_on(limiter.bucket.lock());
+        let start = bucket.last;
+        assert!(bucket.take(start).is_none());
+        assert_eq!(bucket.take(start), Some(Duration::from_secs(100)));
+    }
+}
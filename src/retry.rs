@@ -0,0 +1,65 @@
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How transient REST failures (connection errors, 429s and 5xxs) are retried.
+///
+/// Only idempotent requests are retried: GETs, DELETEs and POSTs which carry a
+/// `client_oid` (so the exchange can't act on them twice).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first.  `1` disables retries.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// The (jittered) delay before retrying after the `attempt`th failure.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.delay_with_jitter(attempt, jitter())
+    }
+
+    // Exponential backoff with "equal jitter": half the delay is fixed, half is random.
+    fn delay_with_jitter(&self, attempt: u32, jitter: f64) -> Duration {
+        let exp = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.checked_mul(exp).unwrap_or(self.max_delay).min(self.max_delay);
+        delay / 2 + delay.mul_f64(jitter) / 2
+    }
+}
+
+// A number in [0, 1).  (No need for a `rand` dependency: v4 UUIDs are random.)
+fn jitter() -> f64 {
+    (Uuid::new_v4().as_u128() as u64 >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay_with_jitter(1, 0.0), Duration::from_millis(125));
+        assert_eq!(policy.delay_with_jitter(1, 1.0), Duration::from_millis(250));
+        assert_eq!(policy.delay_with_jitter(3, 1.0), Duration::from_millis(1000));
+        // Capped...
+        assert_eq!(policy.delay_with_jitter(10, 1.0), policy.max_delay);
+        assert_eq!(policy.delay_with_jitter(100, 0.0), policy.max_delay / 2);
+
+        assert!((0..100).map(|_| jitter()).all(|j| (0.0..1.0).contains(&j)));
+    }
+}
@@ -1,16 +1,20 @@
 use chrono;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::{ borrow::Cow,
            fmt};
 
 use uuid::Uuid;
 
-// use utils::datetime_from_string;
+use crate::utils::datetime_from_string;
+use crate::utils::datetime_opt_from_string;
 use crate::utils::f64_from_string;
+use crate::utils::f64_is_zero;
 use crate::utils::f64_nan_from_string;
 use crate::utils::f64_opt_from_string;
 use crate::utils::usize_from_string;
 use crate::utils::uuid_opt_from_string;
+use crate::book;
+use crate::credentials::Secret;
 use crate::errors;
 
 //////////////////////////////////////////////////////////////////////////////////////
@@ -80,7 +84,7 @@ impl Default for AccountHistoryType {
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(tag = "type", content = "details")]
-// #[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub enum AccountHistoryDetails {
     Fee {
         order_id: Uuid,
@@ -107,7 +111,7 @@ pub enum AccountHistoryDetails {
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-// #[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub enum AccountHistoryDetailsTransferType {
     Deposit,
     Withdraw,
@@ -130,6 +134,7 @@ pub struct AccountHolds {
     pub _ref: Uuid,
     #[serde(rename = "type")]
     pub _type: AccountHoldsType,
+    #[serde(deserialize_with = "f64_from_string")]
     pub amount: f64,
     pub account_id: Uuid,
     pub created_at: DateTime,
@@ -149,7 +154,7 @@ pub enum AccountHoldsType {
 pub struct Auth {
     pub signature: String,
     pub key: String,
-    pub passphrase: String,
+    pub passphrase: Secret,
     pub timestamp: String,
 }
 
@@ -164,11 +169,58 @@ pub struct Candle(
     pub f64, // volume
 );
 
+/// Deposits funds from a Coinbase (not Pro) account.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct CoinbaseAccountDeposit {
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+    pub currency: String,
+    pub coinbase_account_id: Uuid,
+}
+
+/// A stablecoin conversion (e.g. USD to USDC) between two of the profile's accounts.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Conversion {
+    pub id: String,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+    pub from_account_id: Uuid,
+    pub to_account_id: Uuid,
+    pub from: String,
+    pub to: String,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Credentials {
     pub key: String,
-    pub secret: String,
-    pub passphrase: String
+    pub secret: Secret,
+    pub passphrase: Secret
+}
+
+impl Credentials {
+    /// Checks that the credentials are usable: the secret must be base64.
+    pub fn validate(&self) -> Result<(), errors::CBProError> {
+        if self.key.is_empty() || self.passphrase.expose().is_empty() {
+            return Err(errors::CBProError::Credentials("key and passphrase are required".to_string()));
+        }
+        base64::decode(self.secret.expose())
+            .map(|_| ())
+            .map_err(|e| errors::CBProError::Credentials(format!("secret is not base64: {}", e)))
+    }
+}
+
+/// Withdraws funds to a crypto address.  Currencies such as XRP or XLM need a
+/// `destination_tag` (or an explicit `no_destination_tag`).
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct CryptoWithdrawal {
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+    pub currency: String,
+    pub crypto_address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_destination_tag: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
@@ -179,6 +231,29 @@ pub struct Currency {
     pub min_size: f64,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct DepositAddress {
+    pub id: String,
+    pub address: String,
+    #[serde(default)]
+    pub destination_tag: Option<String>,
+    #[serde(default)]
+    pub network: Option<String>,
+    #[serde(deserialize_with = "datetime_from_string")]
+    pub created_at: DateTime,
+}
+
+/// The current fee tier: rates are fractions (0.005 is 0.5%) and `usd_volume` is the
+/// trailing 30-day volume which determines them.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Fees {
+    #[serde(deserialize_with = "f64_from_string")]
+    pub maker_fee_rate: f64,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub taker_fee_rate: f64,
+    #[serde(default, deserialize_with = "f64_opt_from_string")]
+    pub usd_volume: Option<f64>,
+}
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Fill {
@@ -204,6 +279,7 @@ pub enum FillLiquidity {
     T
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Granularity {
     M1 = 60,
     M5 = 300,
@@ -213,8 +289,9 @@ pub enum Granularity {
     D1 = 86400,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Level {
-    // Level1,  // Meh...  Why bother...
+    Level1 = 1,
     Level2 = 2,
     Level3 = 3
 }
@@ -246,19 +323,87 @@ pub enum MarketType {
 // market:{"id":"ea565dc3-1656-49d7-bcdb-d99981ce35a7","size":"0.00100000","product_id":"BTC-USD","side":"buy","stp":"dc","funds":"28.2449436100000000","type":"market","post_only":false,"created_at":"2018-08-23T18:43:18.964413Z","fill_fees":"0.0000000000000000","filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"pending","settled":false}
 // call:[{"id":"063da13d-6aba-45e1-91ca-89f8514da989","price":"100000.00000000","size":"0.00100000","product_id":"BTC-USD","side":"sell","type":"limit","time_in_force":"GTC","post_only":true,"created_at":"2018-08-24T04:50:01.139098Z","fill_fees":"0.0000000000000000","filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"open","settled":false}]
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(remote = "Self")] // See `impl Deserialize for Message`
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum Message {
+    #[serde(skip)]
+    Account(Account),
+    #[serde(skip)]
+    AccountHistory(Vec<AccountHistory>),
+    #[serde(skip)]
+    AccountHolds(Vec<AccountHolds>),
+    #[serde(skip)]
+    Accounts(Vec<Account>),
+    #[serde(skip)]
+    Book {
+        product_id: String,
+        book: book::BookSnapshot,
+    },
+    #[serde(skip)]
+    Candles(Vec<Candle>),
+    #[serde(skip)]
+    CancelledOrders(Vec<Uuid>),
+    #[serde(skip)]
+    Conversion(Conversion),
     Error {
         message: String,
     },
     #[serde(skip)]
+    Currencies(Vec<Currency>),
+    #[serde(skip)]
+    DepositAddress(DepositAddress),
+    #[serde(skip)]
+    Fees(Fees),
+    #[serde(skip)]
+    Fills(Page<Fill>),
+    #[serde(skip)]
     InternalError(errors::CBProError),
     Interval(DateTime),
     None,
+    #[serde(skip)]
+    Order(Order<'static>),
+    #[serde(skip)]
+    Orders(Page<Order<'static>>),
+    #[serde(skip)]
+    ProductTicker {
+        product_id: String,
+        ticker: ProductTicker,
+    },
+    #[serde(skip)]
+    Products(Vec<Product>),
+    #[serde(skip)]
+    Profile(Profile),
+    #[serde(skip)]
+    ProfileTransfer(ProfileTransfer),
+    #[serde(skip)]
+    Profiles(Vec<Profile>),
+    #[serde(skip)]
+    Report(Report),
+    #[serde(skip)]
+    Stats24H {
+        product_id: String,
+        stats: Stats24H,
+    },
     Time(Time),
-    #[serde(rename = "full")]
+    #[serde(skip)]
+    Trades(Vec<Trade>),
+    #[serde(skip)]
+    TrailingVolume(Vec<TrailingVolume>),
+    #[serde(skip)]
+    TransferReceipt(TransferReceipt),
+    #[serde(skip)]
+    Transfers(Page<Transfer>),
+    #[serde(skip)] // Tagged by event ("received", "open"...) rather than channel.
     WSLevel3(WSLevel3),
+    /// The WebSocket (re)connected; any subscriptions have been replayed.
+    #[serde(skip)]
+    WSConnected,
+    /// The WebSocket dropped.  Books built from the feed are stale until resynced.
+    #[serde(skip)]
+    WSDisconnected {
+        reason: String,
+    },
     #[serde(rename = "heartbeat")]
     WSHeartbeat {
         sequence: usize,
@@ -288,6 +433,62 @@ pub enum Message {
     },
     #[serde(rename = "ticker")]
     WSTicker(WSTicker),
+    #[serde(rename = "unsubscribe")]
+    WSUnsubscribe(WSUnsubscribe),
+    #[serde(skip)]
+    WithdrawalFeeEstimate(WithdrawalFeeEstimate),
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        // The full channel's messages are typed by the order event, so pick those out
+        // before deferring to the derived (`remote = "Self"`) impl.  That means buffering
+        // the message first; `Message::from_ws_text` avoids it for WS frames.
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value.get("type").and_then(|t| t.as_str()) {
+            Some(t) if WSLevel3::TYPES.contains(&t) => WSLevel3::deserialize(value).map(Message::WSLevel3),
+            _ => Message::deserialize(value)
+        }.map_err(de::Error::custom)
+    }
+}
+
+impl Message {
+    /// Decodes a WS frame, picking out the full channel's messages by peeking at their type.
+    /// Unlike `Deserialize`, this doesn't build a `serde_json::Value` of every frame first,
+    /// but the frame is still read twice: once by the peek, and again to decode it (which,
+    /// the enums being internally tagged, buffers it anyway).
+    pub fn from_ws_text(text: &str) -> serde_json::Result<Message> {
+        #[derive(Deserialize)]
+        struct Tag<'a> {
+            #[serde(rename = "type", borrow)]
+            _type: &'a str,
+        }
+
+        match serde_json::from_str::<Tag>(text) {
+            Ok(Tag { _type }) if WSLevel3::TYPES.contains(&_type) => serde_json::from_str(text).map(Message::WSLevel3),
+            _ => {
+                let mut deserializer = serde_json::Deserializer::from_str(text);
+                let msg = Message::deserialize(&mut deserializer)?;
+                deserializer.end()?;
+                Ok(msg)
+            }
+        }
+    }
+}
+
+impl Serialize for Message {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        match self {
+            Message::WSLevel3(level3) => level3.serialize(serializer),
+            _ => Message::serialize(self, serializer)
+        }
+    }
 }
 
 /*
@@ -352,24 +553,31 @@ impl From<InputMessage> for Message {
 pub struct Order<'a> {
     #[serde(flatten)]
     pub _type: OrderType,
-    #[serde(flatten)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     client_oid: Option<Uuid>,
+    #[serde(skip_serializing)]
     pub created_at: DateTime,
-    #[serde(flatten, deserialize_with = "f64_opt_from_string")]
+    #[serde(default, deserialize_with = "f64_opt_from_string", skip_serializing)]
     pub executed_value: Option<f64>,
-    #[serde(flatten, deserialize_with = "f64_opt_from_string")]
+    #[serde(default, deserialize_with = "f64_opt_from_string", skip_serializing)]
     pub fill_fees: Option<f64>,
-    #[serde(flatten, deserialize_with = "f64_opt_from_string")]
+    #[serde(default, deserialize_with = "f64_opt_from_string", skip_serializing)]
     pub filled_size: Option<f64>,
+    #[serde(default, skip_serializing)]
     pub id: Option<Uuid>,
+    #[serde(skip_serializing)] // Serialized by `OrderType::Limit`
     pub post_only: bool,
     pub product_id: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_id: Option<Uuid>,
+    #[serde(default, skip_serializing)]
     pub settled: bool,
     pub side: OrderSide,
-    #[serde(flatten)]
+    #[serde(default, skip_serializing)]
     pub status: Option<OrderStatus>,
     #[serde(flatten)]
     pub stop: Option<OrderStop>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stp: Option<String>, // Option because its not in get_orders, but in set_order
 }
 
@@ -396,6 +604,12 @@ impl<'a> Order<'a> {
         Order { client_oid, ..self }
     }
 
+    /// Places the order in `profile_id` rather than the API key's default profile.
+    pub fn profile_id(self, profile_id: Uuid) -> Self {
+        let profile_id = Some(profile_id);
+        Order { profile_id, ..self }
+    }
+
     pub fn stop(self, price: f64, stop_type: OrderStopType) -> Self {
         let stop = Some(OrderStop { stop_price: price, _type: stop_type });
         Order { stop, ..self }
@@ -440,6 +654,7 @@ impl<'a> Order<'a> {
             id: None,
             post_only,
             product_id: product_id.into(),
+            profile_id: None,
             settled: false,
             side,
             status: None,
@@ -465,6 +680,7 @@ impl<'a> Order<'a> {
             id: None,
             post_only: false,
             product_id: product_id.into(),
+            profile_id: None,
             settled: false,
             side,
             status: None,
@@ -506,6 +722,7 @@ impl fmt::Display for OrderStatus {
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct OrderStop {
+    #[serde(deserialize_with = "f64_from_string")]
     stop_price: f64,
     #[serde(rename = "stop")]
     _type: OrderStopType,
@@ -542,6 +759,7 @@ pub enum OrderTimeInForceCancelAfter {
 #[serde(tag = "type")]
 pub enum OrderType {
     Limit {
+        #[serde(default)] // Consumed by `Order::post_only` when deserializing
         post_only: bool,
         #[serde(deserialize_with = "f64_from_string")]
         price: f64,
@@ -551,17 +769,35 @@ pub enum OrderType {
         time_in_force: OrderTimeInForce,
     },
     Market {
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "f64_is_zero")]
         #[serde(deserialize_with = "f64_from_string")]
         size: f64,
         //        #[serde(deserialize_with = "f64_opt_from_string")]
         //        funds: Option<f64>
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "f64_is_zero")]
         #[serde(deserialize_with = "f64_from_string")]
         funds: f64,
     },
 }
 
+/// One page of a paginated REST response.  Coinbase returns `before`/`after`
+/// cursors in the `CB-BEFORE`/`CB-AFTER` headers; `after` points at older items.
+#[derive(Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Deposits funds from a linked bank account (see `/payment-methods`).
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct PaymentMethodDeposit {
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+    pub currency: String,
+    pub payment_method_id: Uuid,
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Product {
     pub id: String,
@@ -575,6 +811,42 @@ pub struct Product {
     pub quote_increment: f64,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct ProductTicker {
+    pub trade_id: usize,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub price: f64,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub size: f64,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub bid: f64,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub ask: f64,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub volume: f64,
+    pub time: DateTime,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Profile {
+    pub id: Uuid,
+    pub user_id: String,
+    pub name: String,
+    pub active: bool,
+    pub is_default: bool,
+    pub created_at: DateTime,
+}
+
+/// Moves `amount` of `currency` between two of the user's profiles.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct ProfileTransfer {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub currency: String,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Reason {
@@ -582,6 +854,96 @@ pub enum Reason {
     Canceled,
 }
 
+/// A report as Coinbase describes it.  `file_url` is set once `status` is `Ready`.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Report {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub _type: ReportType,
+    pub status: ReportStatus,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub created_at: Option<DateTime>,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub completed_at: Option<DateTime>,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub expires_at: Option<DateTime>,
+    #[serde(default)]
+    pub file_url: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Csv,
+    Pdf,
+}
+
+/// Asks Coinbase to generate a report; build with `ReportRequest::fills` or
+/// `ReportRequest::account`.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct ReportRequest {
+    #[serde(rename = "type")]
+    pub _type: ReportType,
+    pub start_date: DateTime,
+    pub end_date: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<Uuid>,
+    pub format: ReportFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl ReportRequest {
+    /// Fills for `product_id` between `start` and `end`.
+    pub fn fills<T: Into<String>>(product_id: T, start: DateTime, end: DateTime) -> Self {
+        ReportRequest { product_id: Some(product_id.into()), ..Self::new(ReportType::Fills, start, end) }
+    }
+
+    /// The statement for `account_id` between `start` and `end`.
+    pub fn account(account_id: Uuid, start: DateTime, end: DateTime) -> Self {
+        ReportRequest { account_id: Some(account_id), ..Self::new(ReportType::Account, start, end) }
+    }
+
+    fn new(_type: ReportType, start_date: DateTime, end_date: DateTime) -> Self {
+        ReportRequest {
+            _type,
+            start_date,
+            end_date,
+            product_id: None,
+            account_id: None,
+            format: ReportFormat::Csv,
+            email: None,
+        }
+    }
+
+    /// Defaults to CSV.
+    pub fn format(self, format: ReportFormat) -> Self {
+        ReportRequest { format, ..self }
+    }
+
+    /// Coinbase also emails the report to `email`.
+    pub fn email<T: Into<String>>(self, email: T) -> Self {
+        ReportRequest { email: Some(email.into()), ..self }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Pending,
+    Creating,
+    Ready,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportType {
+    Fills,
+    Account,
+}
+
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Stats24H {
@@ -593,6 +955,12 @@ pub struct Stats24H {
     pub low: f64,
     #[serde(deserialize_with = "f64_from_string")]
     pub volume: f64,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub last: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub volume_30day: Option<f64>,
 }
 
 
@@ -646,6 +1014,85 @@ pub struct Trade {
     pub side: OrderSide,
 }
 
+/// A deposit or withdrawal, as listed by `/transfers`.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Transfer {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub _type: TransferType,
+    #[serde(default)]
+    pub account_id: Option<Uuid>,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+    #[serde(deserialize_with = "datetime_from_string")]
+    pub created_at: DateTime,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub processed_at: Option<DateTime>,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub completed_at: Option<DateTime>,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub canceled_at: Option<DateTime>,
+    #[serde(default)]
+    pub details: TransferDetails,
+}
+
+/// Which of these are set varies with the kind of transfer (crypto address, Coinbase
+/// account, payment method...).
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct TransferDetails {
+    pub coinbase_account_id: Option<Uuid>,
+    pub coinbase_payment_method_id: Option<Uuid>,
+    pub coinbase_transaction_id: Option<String>,
+    pub coinbase_withdrawal_id: Option<String>,
+    pub crypto_address: Option<String>,
+    pub crypto_transaction_hash: Option<String>,
+    pub crypto_transaction_id: Option<String>,
+    pub destination_tag: Option<String>,
+    pub destination_tag_name: Option<String>,
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub fee: Option<f64>,
+    pub sent_to_address: Option<String>,
+    #[serde(deserialize_with = "f64_opt_from_string")]
+    pub subtotal: Option<f64>,
+}
+
+/// Coinbase's acknowledgement of a deposit or withdrawal.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct TransferReceipt {
+    pub id: Uuid,
+    #[serde(deserialize_with = "f64_from_string")]
+    pub amount: f64,
+    pub currency: String,
+    #[serde(default, deserialize_with = "f64_opt_from_string")]
+    pub fee: Option<f64>,
+    #[serde(default, deserialize_with = "f64_opt_from_string")]
+    pub subtotal: Option<f64>,
+    #[serde(default, deserialize_with = "datetime_opt_from_string")]
+    pub payout_at: Option<DateTime>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferType {
+    Deposit,
+    Withdraw,
+    InternalDeposit,
+    InternalWithdraw,
+}
+
+impl fmt::Display for TransferType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            TransferType::Deposit => "deposit",
+            TransferType::Withdraw => "withdraw",
+            TransferType::InternalDeposit => "internal_deposit",
+            TransferType::InternalWithdraw => "internal_withdraw",
+        };
+        write!(f, "{}", res)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 #[serde(untagged)]
 pub enum WSChannel {
@@ -656,7 +1103,7 @@ pub enum WSChannel {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum WSChannelType {
     Full,
@@ -687,6 +1134,8 @@ pub enum WSLevel2 {
 // Note: this is really the "full" channel but it fits better
 // in the API as "Level3" (since it's kinda actually Level3) so here we are.
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
 pub enum WSLevel3 {
     Activate(WSLevel3Activate),
     Change(WSLevel3Change),
@@ -697,6 +1146,9 @@ pub enum WSLevel3 {
 }
 
 impl WSLevel3 {
+    /// The `type`s of the full channel's messages.
+    pub const TYPES: &'static [&'static str] = &["activate", "change", "done", "match", "open", "received"];
+
     pub fn price(&self) -> Option<&f64> {
         match self {
             WSLevel3::Activate(WSLevel3Activate { .. }) => None,
@@ -735,7 +1187,9 @@ impl WSLevel3 {
             WSLevel3::Received(WSLevel3Received::Market { sequence, .. }) => Some(sequence),
         }
     }
-}#[derive(Debug, Deserialize, PartialEq, Serialize)]
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct WSLevel3Activate {
     pub product_id: String,
     #[serde(deserialize_with = "f64_from_string")]
@@ -857,6 +1311,7 @@ pub enum WSLevel3Received {
         product_id: String,
         sequence: usize,
         order_id: Uuid,
+        #[serde(default)]
         #[serde(deserialize_with = "uuid_opt_from_string")]
         client_oid: Option<Uuid>,
         #[serde(deserialize_with = "f64_from_string")]
@@ -871,6 +1326,7 @@ pub enum WSLevel3Received {
         time: DateTime,
     },
     Market {
+        #[serde(default)]
         #[serde(deserialize_with = "uuid_opt_from_string")]
         client_oid: Option<Uuid>,
         #[serde(default)]
@@ -961,6 +1417,18 @@ impl WSTicker {
     }
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename = "unsubscribe")]
+pub struct WSUnsubscribe {
+    pub channels: Vec<WSChannel>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct WithdrawalFeeEstimate {
+    #[serde(deserialize_with = "f64_from_string")]
+    pub fee: f64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -979,9 +1447,11 @@ mod tests {
 
         let o = Order::buy_limit("BTC-USD", 10.0, 100.0, true)
             .client_oid(Uuid::nil())
+            .profile_id(Uuid::nil())
             .stop_loss(99.0)
             .time_in_force(OrderTimeInForce::GTC);
         assert!(o.client_oid.is_some());
+        assert_eq!(o.profile_id, Some(Uuid::nil()));
         assert!(o.stop.is_some());
 
         match &o._type {
@@ -990,6 +1460,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_order_json() {
+        let json = |o: &Order| serde_json::to_value(o).unwrap();
+        assert_eq!(json(&Order::buy_limit("BTC-USD", 1.0, 100.0, true)),
+                   serde_json::json!({"type": "limit", "product_id": "BTC-USD", "side": "buy", "size": 1.0,
+                                      "price": 100.0, "post_only": true, "time_in_force": "GTC"}));
+        assert_eq!(json(&Order::sell_market("BTC-USD", 0.5)),
+                   serde_json::json!({"type": "market", "product_id": "BTC-USD", "side": "sell", "size": 0.5}));
+        assert_eq!(json(&Order::sell_limit("BTC-USD", 1.0, 90.0, false).stop_loss(95.0).client_oid(Uuid::nil())),
+                   serde_json::json!({"type": "limit", "product_id": "BTC-USD", "side": "sell", "size": 1.0,
+                                      "price": 90.0, "post_only": false, "time_in_force": "GTC",
+                                      "stop": "loss", "stop_price": 95.0,
+                                      "client_oid": "00000000-0000-0000-0000-000000000000"}));
+        let gtt = OrderTimeInForce::GTT { cancel_after: OrderTimeInForceCancelAfter::Hour };
+        assert_eq!(json(&Order::buy_limit("BTC-USD", 1.0, 100.0, false).time_in_force(gtt)),
+                   serde_json::json!({"type": "limit", "product_id": "BTC-USD", "side": "buy", "size": 1.0,
+                                      "price": 100.0, "post_only": false, "time_in_force": "GTT",
+                                      "cancel_after": "hour"}));
+    }
+
+    #[test]
+    fn test_parse_order_ack() {
+        // The acknowledgements above `Order`.
+        let json = r#"{"id":"e9d0ff7a-ed50-4040-87a7-c884ae562807","price":"1.12000000","size":"1.00000000",
+                       "product_id":"BTC-USD","side":"buy","stp":"dc","type":"limit","time_in_force":"GTC",
+                       "post_only":true,"created_at":"2018-08-23T18:53:42.144811Z","fill_fees":"0.0000000000000000",
+                       "filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"pending",
+                       "settled":false}"#;
+        let o: Order = serde_json::from_str(json).unwrap();
+        assert_eq!(o.id, Some(Uuid::from_str("e9d0ff7a-ed50-4040-87a7-c884ae562807").unwrap()));
+        assert!(o.post_only);
+        assert_eq!(o.status, Some(OrderStatus::Pending));
+        assert_eq!(o.stp.as_deref(), Some("dc"));
+        match o._type {
+            OrderType::Limit { price, size, time_in_force: OrderTimeInForce::GTC, .. } => {
+                assert_eq!(price, 1.12);
+                assert_eq!(size, 1.0);
+            },
+            _type => panic!("not a GTC limit order: {:?}", _type)
+        }
+
+        let json = r#"{"id":"ea565dc3-1656-49d7-bcdb-d99981ce35a7","size":"0.00100000","product_id":"BTC-USD",
+                       "side":"buy","stp":"dc","funds":"28.2449436100000000","type":"market","post_only":false,
+                       "created_at":"2018-08-23T18:43:18.964413Z","fill_fees":"0.0000000000000000",
+                       "filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"pending",
+                       "settled":false}"#;
+        let o: Order = serde_json::from_str(json).unwrap();
+        assert_eq!(o._type, OrderType::Market { size: 0.001, funds: 28.24494361 });
+        assert_eq!(o.filled_size, Some(0.0));
+
+        let json = r#"[{"id":"063da13d-6aba-45e1-91ca-89f8514da989","price":"100000.00000000","size":"0.00100000",
+                        "product_id":"BTC-USD","side":"sell","type":"limit","time_in_force":"GTC","post_only":true,
+                        "created_at":"2018-08-24T04:50:01.139098Z","fill_fees":"0.0000000000000000",
+                        "filled_size":"0.00000000","executed_value":"0.0000000000000000","status":"open",
+                        "settled":false}]"#;
+        let orders: Vec<Order> = serde_json::from_str(json).unwrap();
+        assert_eq!(orders[0].side, OrderSide::Sell);
+        assert_eq!(orders[0].status, Some(OrderStatus::Open));
+        assert!(orders[0].stp.is_none());
+    }
+
     #[derive(Debug, Serialize)]
     enum Coin { AAA, BBB }
 
@@ -1089,6 +1620,51 @@ mod tests {
         assert!(str.contains("profile_id: None"));
     }
 
+    #[test]
+    fn test_level3_messages() {
+        // Coinbase's `received` samples, which carry no `client_oid`.
+        let json = r#"{"type":"received","time":"2014-11-07T08:19:27.028459Z","product_id":"BTC-USD","sequence":10,
+                       "order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","size":"1.34","price":"502.1","side":"buy",
+                       "order_type":"limit"}"#;
+        match Message::from_ws_text(json).unwrap() {
+            Message::WSLevel3(WSLevel3::Received(WSLevel3Received::Limit { price, client_oid, .. })) => {
+                assert_eq!(price, 502.1);
+                assert!(client_oid.is_none());
+            },
+            m => panic!("not a limit order received: {:?}", m)
+        }
+
+        let json = r#"{"type":"received","time":"2014-11-09T08:19:27.028459Z","product_id":"BTC-USD","sequence":12,
+                       "order_id":"dddec984-77a8-460a-b958-66f114b0de9b","funds":"3000.234","side":"buy",
+                       "order_type":"market"}"#;
+        match Message::from_ws_text(json).unwrap() {
+            Message::WSLevel3(WSLevel3::Received(WSLevel3Received::Market { funds, client_oid, .. })) => {
+                assert_eq!(funds, Some(3000.234));
+                assert!(client_oid.is_none());
+            },
+            m => panic!("not a market order received: {:?}", m)
+        }
+
+        let json = r#"{"type":"open","time":"2014-11-07T08:19:27.028459Z","product_id":"BTC-USD","sequence":10,
+                       "order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","price":"200.2","remaining_size":"1.00","side":"sell"}"#;
+        let m: Message = serde_json::from_str(json).unwrap();
+        assert!(matches!(m, Message::WSLevel3(WSLevel3::Open(..))));
+
+        let json = r#"{"type":"match","trade_id":10,"sequence":50,"maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8",
+                       "taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","time":"2014-11-07T08:19:27.028459Z",
+                       "product_id":"BTC-USD","size":"5.23512","price":"400.23","side":"sell"}"#;
+        match serde_json::from_str(json).unwrap() {
+            Message::WSLevel3(level3) => assert_eq!(level3.price(), Some(&400.23)),
+            m => panic!("not a level3 message: {:?}", m)
+        }
+        assert_eq!(Message::from_ws_text(json).unwrap(), serde_json::from_str::<Message>(json).unwrap());
+
+        let json = r#"{"type":"heartbeat","sequence":90,"last_trade_id":20,"product_id":"BTC-USD",
+                       "time":"2014-11-07T08:19:28.464459Z"}"#;
+        assert!(matches!(Message::from_ws_text(json).unwrap(), Message::WSHeartbeat { sequence: 90, .. }));
+        assert!(Message::from_ws_text(&format!("{} trailing", json)).is_err());
+    }
+
     #[test]
     fn test_parse_uuid() {
         #[derive(Debug, Deserialize, Serialize)]
@@ -1111,5 +1687,67 @@ mod tests {
 
         assert!(s.uuid.is_none());
     }
+
+    #[test]
+    fn test_parse_account_history() {
+        // Coinbase's `/accounts/{id}/ledger` sample, plus a transfer.
+        let json = r#"[{"id":"100","created_at":"2014-11-07T08:19:27.028459Z","amount":"0.001","balance":"239.669",
+                        "type":"fee","details":{"order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","trade_id":"74",
+                                                "product_id":"BTC-USD"}},
+                       {"id":"101","created_at":"2014-11-07T08:20:27.028459Z","amount":"10.0","balance":"249.669",
+                        "type":"transfer","details":{"transfer_id":"19ac524d-8827-4246-a1b2-18dc5ca9472c",
+                                                     "transfer_type":"deposit"}}]"#;
+        let history: Vec<AccountHistory> = serde_json::from_str(json).unwrap();
+        assert_eq!(history[0].id, 100);
+        assert_eq!(history[0].balance, 239.669);
+        match &history[0].details {
+            AccountHistoryDetails::Fee { trade_id, product_id, .. } => {
+                assert_eq!(*trade_id, 74);
+                assert_eq!(product_id, "BTC-USD");
+            },
+            details => panic!("not a fee: {:?}", details)
+        }
+        assert_eq!(AccountHistoryType::from(&history[0].details), AccountHistoryType::Fee);
+        assert!(matches!(history[1].details,
+                         AccountHistoryDetails::Transfer { transfer_type: AccountHistoryDetailsTransferType::Deposit, .. }));
+    }
+
+    #[test]
+    fn test_parse_transfer() {
+        let json = r#"{"id":"19ac524d-8827-4246-a1b2-18dc5ca9472c","type":"withdraw",
+                       "created_at":"2020-03-12 00:14:12.397805+00","completed_at":"2020-03-12 00:14:13.021323+00",
+                       "canceled_at":null,"processed_at":"2020-03-12 00:14:13.021323+00",
+                       "account_id":"2fec40ac-525b-4192-871a-39d784945055","user_nonce":"1584023650800",
+                       "amount":"1.00000000","details":{"fee":"0.00000000","crypto_address":"3PGAGh"}}"#;
+        let t: Transfer = serde_json::from_str(json).unwrap();
+        assert_eq!(t._type, TransferType::Withdraw);
+        assert_eq!(t.amount, 1.0);
+        assert_eq!(t.created_at.to_rfc3339(), "2020-03-12T00:14:12.397805+00:00");
+        assert!(t.completed_at.is_some());
+        assert!(t.canceled_at.is_none());
+        assert_eq!(t.details.crypto_address.as_deref(), Some("3PGAGh"));
+        assert_eq!(t.details.fee, Some(0.0));
+        assert!(t.details.coinbase_account_id.is_none());
+
+        let json = r#"{"id":"2fec40ac-525b-4192-871a-39d784945055","type":"deposit",
+                       "created_at":"2020-03-12 00:14:12.397805+00","amount":"10.00","canceled_at":null,
+                       "details":{"coinbase_account_id":"19ac524d-8827-4246-a1b2-18dc5ca9472c",
+                                  "coinbase_transaction_id":"5e697ed49f8417148f3366ea","crypto_address":null}}"#;
+        let t: Transfer = serde_json::from_str(json).unwrap();
+        assert_eq!(t._type, TransferType::Deposit);
+        assert!(t.details.coinbase_account_id.is_some());
+        assert!(t.details.crypto_address.is_none());
+
+        // Amounts in request bodies are numbers, as in orders.
+        let withdrawal = CryptoWithdrawal {
+            amount: 1.5,
+            currency: "BTC".to_string(),
+            crypto_address: "3PGAGh".to_string(),
+            destination_tag: None,
+            no_destination_tag: None,
+        };
+        assert_eq!(serde_json::to_value(&withdrawal).unwrap(),
+                   serde_json::json!({"amount": 1.5, "currency": "BTC", "crypto_address": "3PGAGh"}));
+    }
 }
 
@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::structs::{WSChannel, WSChannelType};
+
+/// The WS channels asked for (`subscribe` minus `unsubscribe`) and the channels Coinbase
+/// last confirmed (its `subscriptions` message), per channel and product.
+///
+/// The two differ while requests are in flight; after that, any difference means Coinbase
+/// didn't honour a request (e.g. an unknown product).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Subscriptions {
+    requested: BTreeMap<WSChannelType, BTreeSet<String>>,
+    confirmed: BTreeMap<WSChannelType, BTreeSet<String>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, channels: &[WSChannel]) {
+        for (name, product_ids) in channels.iter().map(_split) {
+            self.requested.entry(name).or_default().extend(product_ids);
+        }
+    }
+
+    /// A bare channel name drops the whole channel; otherwise only the listed products.
+    pub fn unsubscribe(&mut self, channels: &[WSChannel]) {
+        for channel in channels {
+            match channel {
+                WSChannel::Name(name) => {
+                    self.requested.remove(name);
+                },
+                WSChannel::WithProduct { name, product_ids } => {
+                    if let Some(subscribed) = self.requested.get_mut(name) {
+                        product_ids.iter().for_each(|p| { subscribed.remove(p); });
+                        if subscribed.is_empty() {
+                            self.requested.remove(name);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records Coinbase's view, which replaces the previous one.
+    pub fn confirm(&mut self, channels: &[WSChannel]) {
+        self.confirmed = _collect(channels.iter().map(_split));
+    }
+
+    /// Forgets the confirmations (e.g. when the WebSocket drops).
+    pub fn disconnected(&mut self) {
+        self.confirmed.clear();
+    }
+
+    pub fn requested(&self) -> Vec<WSChannel> {
+        _channels(&self.requested)
+    }
+
+    pub fn confirmed(&self) -> Vec<WSChannel> {
+        _channels(&self.confirmed)
+    }
+
+    /// Requested but not (yet) confirmed.
+    pub fn pending(&self) -> Vec<WSChannel> {
+        _channels(&_difference(&self.requested, &self.confirmed))
+    }
+
+    /// Confirmed but no longer requested.
+    pub fn unexpected(&self) -> Vec<WSChannel> {
+        _channels(&_difference(&self.confirmed, &self.requested))
+    }
+
+    /// Whether every one of `channels` has already been requested.
+    pub fn covers(&self, channels: &[WSChannel]) -> bool {
+        _difference(&_collect(channels.iter().map(_split)), &self.requested).is_empty()
+    }
+
+    pub fn is_reconciled(&self) -> bool {
+        self.requested == self.confirmed
+    }
+}
+
+fn _split(channel: &WSChannel) -> (WSChannelType, BTreeSet<String>) {
+    match channel {
+        WSChannel::Name(name) => (name.clone(), BTreeSet::new()),
+        WSChannel::WithProduct { name, product_ids } => (name.clone(), product_ids.iter().cloned().collect())
+    }
+}
+
+fn _collect<I>(channels: I) -> BTreeMap<WSChannelType, BTreeSet<String>>
+    where I: Iterator<Item = (WSChannelType, BTreeSet<String>)> {
+    let mut collected: BTreeMap<WSChannelType, BTreeSet<String>> = BTreeMap::new();
+    for (name, product_ids) in channels {
+        collected.entry(name).or_default().extend(product_ids);
+    }
+    collected
+}
+
+fn _difference(a: &BTreeMap<WSChannelType, BTreeSet<String>>, b: &BTreeMap<WSChannelType, BTreeSet<String>>)
+    -> BTreeMap<WSChannelType, BTreeSet<String>> {
+    a.iter()
+        .filter_map(|(name, product_ids)| match b.get(name) {
+            None => Some((name.clone(), product_ids.clone())),
+            Some(other) => {
+                let missing: BTreeSet<String> = product_ids.difference(other).cloned().collect();
+                if missing.is_empty() { None } else { Some((name.clone(), missing)) }
+            }
+        })
+        .collect()
+}
+
+fn _channels(channels: &BTreeMap<WSChannelType, BTreeSet<String>>) -> Vec<WSChannel> {
+    channels.iter()
+        .map(|(name, product_ids)| {
+            if product_ids.is_empty() {
+                WSChannel::Name(name.clone())
+            } else {
+                WSChannel::WithProduct { name: name.clone(), product_ids: product_ids.iter().cloned().collect() }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_product(name: WSChannelType, product_ids: &[&str]) -> WSChannel {
+        WSChannel::WithProduct { name, product_ids: product_ids.iter().map(|p| p.to_string()).collect() }
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.subscribe(&[with_product(WSChannelType::Level2, &["BTC-USD", "ETH-USD"]),
+                                  WSChannel::Name(WSChannelType::Status)]);
+        subscriptions.subscribe(&[with_product(WSChannelType::Level2, &["LTC-USD"])]);
+        assert_eq!(subscriptions.requested(),
+                   vec!(with_product(WSChannelType::Level2, &["BTC-USD", "ETH-USD", "LTC-USD"]),
+                        WSChannel::Name(WSChannelType::Status)));
+
+        // Coinbase hasn't caught up with LTC-USD (nor with the status channel).
+        subscriptions.confirm(&[with_product(WSChannelType::Level2, &["BTC-USD", "ETH-USD"])]);
+        assert!(!subscriptions.is_reconciled());
+        assert_eq!(subscriptions.pending(),
+                   vec!(with_product(WSChannelType::Level2, &["LTC-USD"]),
+                        WSChannel::Name(WSChannelType::Status)));
+        assert!(subscriptions.unexpected().is_empty());
+
+        subscriptions.unsubscribe(&[with_product(WSChannelType::Level2, &["ETH-USD", "LTC-USD"]),
+                                    WSChannel::Name(WSChannelType::Status)]);
+        assert!(subscriptions.pending().is_empty());
+        assert_eq!(subscriptions.unexpected(), vec!(with_product(WSChannelType::Level2, &["ETH-USD"])));
+
+        subscriptions.confirm(&[with_product(WSChannelType::Level2, &["BTC-USD"])]);
+        assert!(subscriptions.is_reconciled());
+
+        subscriptions.disconnected();
+        assert_eq!(subscriptions.pending(), subscriptions.requested());
+    }
+
+    #[test]
+    fn test_covers() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.subscribe(&[with_product(WSChannelType::Level2, &["BTC-USD", "ETH-USD"]),
+                                  WSChannel::Name(WSChannelType::Status)]);
+        assert!(subscriptions.covers(&[with_product(WSChannelType::Level2, &["ETH-USD"])]));
+        assert!(subscriptions.covers(&[WSChannel::Name(WSChannelType::Status)]));
+        assert!(!subscriptions.covers(&[with_product(WSChannelType::Level2, &["ETH-USD", "LTC-USD"])]));
+        assert!(!subscriptions.covers(&[WSChannel::Name(WSChannelType::Heartbeat)]));
+
+        subscriptions.unsubscribe(&[with_product(WSChannelType::Level2, &["ETH-USD"])]);
+        assert!(!subscriptions.covers(&[with_product(WSChannelType::Level2, &["ETH-USD"])]));
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::de::{self, Deserialize, Deserializer, Visitor};
 use std::fmt;
 use std::str::FromStr;
@@ -60,6 +61,36 @@ where
     d.deserialize_any(F64InQuotes).or(Ok(std::f64::NAN)) // not sure that 100% correct
 }
 
+pub fn f64_is_zero(v: &f64) -> bool {
+    *v == 0.0
+}
+
+/// Parses RFC3339 or the Postgres-ish timestamps (e.g. "2019-06-18 01:37:48.78953+00")
+/// which the transfer endpoints return.
+fn parse_datetime(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%#z"))
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+pub fn datetime_from_string<'de, D>(d: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(d)?;
+    parse_datetime(&s).map_err(de::Error::custom)
+}
+
+pub fn datetime_opt_from_string<'de, D>(d: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(d)? {
+        Some(s) => parse_datetime(&s).map_err(de::Error::custom).map(Some),
+        None => Ok(None),
+    }
+}
+
 struct UsizeInQuotes;
 
 impl<'de> Visitor<'de> for UsizeInQuotes {